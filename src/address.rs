@@ -0,0 +1,117 @@
+//! Addresses of the gRPC endpoints, following the
+//! [gRPC naming](https://github.com/grpc/grpc/blob/master/doc/naming.md) of Unix domain sockets:
+//! `unix:path`, `unix:///absolute/path` and `unix-abstract:name`, only on Unix.
+
+#[cfg(unix)]
+use std::{io, os::unix::fs::FileTypeExt, path::PathBuf};
+use std::{net::SocketAddr, str::FromStr};
+
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+/// A Unix domain socket address.
+#[cfg(unix)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnixAddr {
+    Path(PathBuf),
+    /// Linux abstract namespace, the name doesn't include the leading NUL byte.
+    Abstract(String),
+}
+
+#[cfg(unix)]
+impl UnixAddr {
+    /// Parse `unix:` and `unix-abstract:` addresses, `None` for other schemes.
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Some(name) = s.strip_prefix("unix-abstract:") {
+            Some(UnixAddr::Abstract(name.to_string()))
+        } else if let Some(path) = s.strip_prefix("unix://") {
+            Some(UnixAddr::Path(PathBuf::from(path)))
+        } else {
+            s.strip_prefix("unix:")
+                .map(|path| UnixAddr::Path(PathBuf::from(path)))
+        }
+    }
+
+    /// Bind the address, a stale socket file left by a previous run is replaced.
+    pub fn bind(&self) -> io::Result<UnixListener> {
+        match self {
+            UnixAddr::Path(path) => {
+                if std::fs::metadata(path).is_ok_and(|x| x.file_type().is_socket()) {
+                    std::fs::remove_file(path)?;
+                }
+                UnixListener::bind(path)
+            }
+            UnixAddr::Abstract(name) => {
+                let listener = std::os::unix::net::UnixListener::bind_addr(&abstract_addr(name)?)?;
+                listener.set_nonblocking(true)?;
+                UnixListener::from_std(listener)
+            }
+        }
+    }
+
+    pub async fn connect(&self) -> io::Result<UnixStream> {
+        match self {
+            UnixAddr::Path(path) => UnixStream::connect(path).await,
+            UnixAddr::Abstract(name) => {
+                // connecting to a local socket doesn't block for long
+                let stream = std::os::unix::net::UnixStream::connect_addr(&abstract_addr(name)?)?;
+                stream.set_nonblocking(true)?;
+                UnixStream::from_std(stream)
+            }
+        }
+    }
+}
+
+/// Reject the Unix domain socket addresses on the platforms without them.
+#[cfg(not(unix))]
+pub fn reject_unix(s: &str) -> anyhow::Result<()> {
+    if s.starts_with("unix:") || s.starts_with("unix-abstract:") {
+        anyhow::bail!("Unix domain sockets are not supported on this platform");
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn abstract_addr(name: &str) -> io::Result<std::os::unix::net::SocketAddr> {
+    #[cfg(target_os = "android")]
+    use std::os::android::net::SocketAddrExt;
+    #[cfg(target_os = "linux")]
+    use std::os::linux::net::SocketAddrExt;
+
+    std::os::unix::net::SocketAddr::from_abstract_name(name)
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn abstract_addr(_name: &str) -> io::Result<std::os::unix::net::SocketAddr> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "abstract Unix domain sockets are only supported on Linux",
+    ))
+}
+
+/// The listening address of the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BindAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(UnixAddr),
+}
+
+impl FromStr for BindAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #[cfg(unix)]
+        if let Some(addr) = UnixAddr::parse(s) {
+            return Ok(BindAddr::Unix(addr));
+        }
+        #[cfg(not(unix))]
+        reject_unix(s)?;
+
+        s.parse().map(BindAddr::Tcp).map_err(|_| {
+            anyhow::anyhow!(
+                "invalid address, expect a socket address, e.g. `[::]:50051`, `unix:path` or `unix-abstract:name`"
+            )
+        })
+    }
+}
@@ -0,0 +1,90 @@
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, MessageDescriptor, ReflectMessage, Value, prost_types::Any,
+};
+
+const ANY_MESSAGE_NAME: &str = "google.protobuf.Any";
+
+/// Fail with the list of `google.protobuf.Any` type URLs which can't be resolved in the pool.
+pub fn ensure_type_urls_resolved(type_urls: Vec<String>) -> anyhow::Result<()> {
+    if type_urls.is_empty() {
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "unknown `google.protobuf.Any` type URLs, add the descriptor sets defining them: {}",
+        type_urls.join(", ")
+    )
+}
+
+/// Collect the unresolvable type URLs of all `Any` fields in the message, including nested payloads.
+pub fn unresolved_type_urls(msg: &DynamicMessage) -> Vec<String> {
+    let mut type_urls = Vec::new();
+    collect_message(msg, &mut type_urls);
+    type_urls.sort();
+    type_urls.dedup();
+    type_urls
+}
+
+/// Same as [`unresolved_type_urls`], but for the `@type` of JSON objects.
+pub fn unresolved_json_type_urls(pool: &DescriptorPool, value: &serde_json::Value) -> Vec<String> {
+    let mut type_urls = Vec::new();
+    collect_json(pool, value, &mut type_urls);
+    type_urls.sort();
+    type_urls.dedup();
+    type_urls
+}
+
+fn resolve(pool: &DescriptorPool, type_url: &str) -> Option<MessageDescriptor> {
+    let (_, name) = type_url.rsplit_once('/')?;
+    pool.get_message_by_name(name)
+}
+
+fn collect_message(msg: &DynamicMessage, type_urls: &mut Vec<String>) {
+    let desc = msg.descriptor();
+
+    if desc.full_name() == ANY_MESSAGE_NAME {
+        let Ok(any) = msg.transcode_to::<Any>() else {
+            return;
+        };
+
+        match resolve(desc.parent_pool(), &any.type_url) {
+            Some(payload_desc) => {
+                if let Ok(payload) = DynamicMessage::decode(payload_desc, any.value.as_slice()) {
+                    collect_message(&payload, type_urls);
+                }
+            }
+            None => type_urls.push(any.type_url),
+        }
+        return;
+    }
+
+    for (_, value) in msg.fields() {
+        collect_value(value, type_urls);
+    }
+}
+
+fn collect_value(value: &Value, type_urls: &mut Vec<String>) {
+    match value {
+        Value::Message(msg) => collect_message(msg, type_urls),
+        Value::List(values) => values.iter().for_each(|x| collect_value(x, type_urls)),
+        Value::Map(values) => values.values().for_each(|x| collect_value(x, type_urls)),
+        _ => {}
+    }
+}
+
+fn collect_json(pool: &DescriptorPool, value: &serde_json::Value, type_urls: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            if let Some(serde_json::Value::String(type_url)) = map.get("@type")
+                && resolve(pool, type_url).is_none()
+            {
+                type_urls.push(type_url.clone());
+            }
+            map.values().for_each(|x| collect_json(pool, x, type_urls));
+        }
+        serde_json::Value::Array(values) => {
+            values.iter().for_each(|x| collect_json(pool, x, type_urls))
+        }
+        _ => {}
+    }
+}
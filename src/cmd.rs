@@ -1,5 +1,33 @@
+/// The [`TransportSettings`](crate::connect::TransportSettings) of a command, from its transport options.
+/// `argh` can't share the options between the commands, so they are declared in each of them
+/// with the same names and types: `proxy`, `plaintext`, the keepalive, the flow control windows and
+/// the TCP options. Only the commands speaking the protocols translated from gRPC have `http1`.
+macro_rules! transport_settings {
+    ($cmd:expr) => {
+        transport_settings!($cmd, http1: false)
+    };
+    ($cmd:expr, http1: $http1:expr) => {
+        $crate::connect::TransportSettings {
+            proxy: $cmd.proxy.clone(),
+            http1: $http1,
+            keepalive_interval: $cmd.keepalive_interval,
+            keepalive_timeout: $cmd.keepalive_timeout,
+            keepalive_while_idle: $cmd.keepalive_while_idle,
+            initial_stream_window_size: $cmd.initial_stream_window_size,
+            initial_connection_window_size: $cmd.initial_connection_window_size,
+            adaptive_window: $cmd.adaptive_window,
+            tcp_nodelay: !$cmd.disable_tcp_nodelay,
+            tcp_keepalive: $cmd.tcp_keepalive,
+            plaintext: $cmd.plaintext,
+        }
+    };
+}
+
+mod bench;
 mod client;
 mod compile;
+mod gateway;
+mod health;
 mod inspect;
 mod json;
 mod server;
@@ -15,6 +43,9 @@ pub enum Command {
     Inspect(inspect::InspectCommand),
     Server(server::ServerCommand),
     Client(client::ClientCommand),
+    Bench(bench::BenchCommand),
+    Gateway(gateway::GatewayCommand),
+    Health(health::HealthCommand),
     Json(json::JsonCommand),
     Version(version::VersionCommand),
 }
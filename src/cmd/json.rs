@@ -4,33 +4,51 @@ use std::{
 };
 
 use argh::FromArgs;
-use base64::{Engine, prelude::BASE64_STANDARD};
 use prost::Message;
-use prost_reflect::DynamicMessage;
+use prost_reflect::{DeserializeOptions, DynamicMessage};
 
 use super::Executable;
-use crate::descriptor_set::DescriptorSet;
+use crate::{
+    descriptor_set::DescriptorSet,
+    encoding::{BinaryEncoding, InputEncoding},
+    format::MessageFormat,
+    framing::Framing,
+    raw::decode_raw,
+    unknown::UnknownFieldsMode,
+};
 
-/// convert data between protobuf binary data and JSON
+/// convert data between protobuf binary data and JSON or text format
 #[derive(FromArgs, Clone, Debug)]
 #[argh(subcommand, name = "json")]
 pub struct JsonCommand {
     /// the path to the grpc proto descriptor set file. could be generated by `protoc` or `compile` command of this tool.
+    /// could be defined multiple times to resolve `google.protobuf.Any` payloads from other descriptor sets.
+    /// not required in `raw` mode.
     #[argh(option, short = 'D')]
-    descriptor_set: PathBuf,
+    descriptor_set: Vec<PathBuf>,
 
-    /// protobuf message type name, e.g. `helloworld.Greeter.SayHelloRequest`.
+    /// protobuf message type name, e.g. `helloworld.Greeter.SayHelloRequest`. not required in `raw` mode.
     #[argh(positional)]
-    message: String,
+    message: Option<String>,
 
-    /// reverse the conversion. input is JSON, output is protobuf.
+    /// reverse the conversion. input is JSON or text format, output is protobuf.
     #[argh(switch, short = 'j')]
     json_to_protobuf: bool,
 
-    /// encode the output to base64. this option is only valid when `json_to_protobuf` is set.
+    /// encode the output to base64, same as `--output-encoding base64`.
     #[argh(switch, short = 'b')]
     base64: bool,
 
+    /// the encoding of the protobuf binary input, could be `raw`, `base64`, `base64url`, `hex`, `c-escape`
+    /// or `auto` to guess it from the input. The default is `raw`.
+    #[argh(option, default = "InputEncoding::default()")]
+    input_encoding: InputEncoding,
+
+    /// the encoding of the protobuf binary output, could be `raw`, `base64`, `base64url`, `hex` or `c-escape`.
+    /// this option is only valid when `json_to_protobuf` is set. The default is `raw`.
+    #[argh(option)]
+    output_encoding: Option<BinaryEncoding>,
+
     /// the path to the output file. leave empty means write to stdout.
     #[argh(option, short = 'o')]
     output: Option<PathBuf>,
@@ -38,17 +56,36 @@ pub struct JsonCommand {
     /// the path to the input file. leave empty means read from stdin.
     #[argh(option, short = 'i')]
     input: Option<PathBuf>,
+
+    /// the readable side format, could be `json` or `text`.
+    /// guessed from the file extension if not set, defaults to `json`.
+    #[argh(option, short = 'f')]
+    format: Option<MessageFormat>,
+
+    /// decode the protobuf binary data without schema, print the fields with their number,
+    /// wire type and all possible interpretations in JSON.
+    #[argh(switch, short = 'r')]
+    raw: bool,
+
+    /// the framing of the protobuf binary stream, could be `none`, `delimited` (varint length prefixed)
+    /// or `grpc` (gRPC message frames, compressed frames are assumed to be gzip).
+    /// with framing, the readable side is a sequence of JSON messages, or text format messages
+    /// one per line. The default is `none`.
+    #[argh(option, default = "Framing::None")]
+    framing: Framing,
+
+    /// what to do with the fields of the decoded message missing from the schema,
+    /// could be `ignore`, `warn` or `error`. The default is `ignore`.
+    #[argh(option, default = "UnknownFieldsMode::Ignore")]
+    unknown_fields: UnknownFieldsMode,
+
+    /// render the fields missing from the schema in the output, under `@unknownFields` in JSON.
+    #[argh(switch)]
+    show_unknown_fields: bool,
 }
 
 impl Executable for JsonCommand {
     fn run(&self) -> anyhow::Result<()> {
-        let ds = DescriptorSet::from_file(&self.descriptor_set)?;
-        let pool = ds.pool();
-
-        let msg_type = pool
-            .get_message_by_name(&self.message)
-            .ok_or_else(|| anyhow::anyhow!("Message not found: {}", self.message))?;
-
         let input = if let Some(input) = &self.input {
             std::fs::read(input)?
         } else {
@@ -57,22 +94,141 @@ impl Executable for JsonCommand {
             buf
         };
 
-        let data = if self.json_to_protobuf {
-            let mut json_de = serde_json::de::Deserializer::from_slice(&input);
-            let msg = DynamicMessage::deserialize(msg_type, &mut json_de)?;
-            json_de.end()?;
-            let bin_message = msg.encode_to_vec();
+        let input = if self.json_to_protobuf {
+            input
+        } else {
+            self.input_encoding.decode(&input)?
+        };
 
-            if self.base64 {
-                BASE64_STANDARD.encode(bin_message).into_bytes()
-            } else {
-                bin_message
+        if self.raw {
+            if self.json_to_protobuf {
+                anyhow::bail!("`raw` mode only supports protobuf to JSON conversion");
             }
+            let lines = self
+                .framing
+                .split(&input)?
+                .iter()
+                .map(|x| Ok(serde_json::to_string(&decode_raw(x)?)?))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            return self.write_output(self.join_readable(lines));
+        }
+
+        let Some(message) = self
+            .message
+            .as_ref()
+            .filter(|_| !self.descriptor_set.is_empty())
+        else {
+            anyhow::bail!("descriptor set and message type are required unless `raw` is set");
+        };
+
+        let ds = DescriptorSet::from_files(&self.descriptor_set)?;
+        let pool = ds.pool();
+
+        let msg_type = pool
+            .get_message_by_name(message)
+            .ok_or_else(|| anyhow::anyhow!("Message not found: {message}"))?;
+
+        let readable_path = if self.json_to_protobuf {
+            &self.input
         } else {
-            let msg = DynamicMessage::decode(msg_type, input.as_slice())?;
-            serde_json::to_vec(&msg)?
+            &self.output
         };
+        let format = self
+            .format
+            .or_else(|| readable_path.as_deref().and_then(MessageFormat::from_path))
+            .unwrap_or_default();
 
+        let data = if self.json_to_protobuf {
+            let messages = self
+                .split_readable(std::str::from_utf8(&input)?, format)?
+                .into_iter()
+                .map(|x| {
+                    let msg = format.parse(msg_type.clone(), x, &DeserializeOptions::new())?;
+                    Ok(msg.encode_to_vec())
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let output_encoding = self.output_encoding.unwrap_or(if self.base64 {
+                BinaryEncoding::Base64
+            } else {
+                BinaryEncoding::Raw
+            });
+
+            output_encoding.encode(&self.framing.join(messages)?)
+        } else {
+            let lines = self
+                .framing
+                .split(&input)?
+                .into_iter()
+                .map(|x| {
+                    let msg = DynamicMessage::decode(msg_type.clone(), x.as_slice())?;
+                    self.unknown_fields.check(&msg)?;
+                    format.print(&msg, self.show_unknown_fields)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            self.join_readable(lines)
+        };
+
+        self.write_output(data)
+    }
+}
+
+impl JsonCommand {
+    /// Without framing the whole input is a single message. Otherwise the JSON messages follow each other,
+    /// pretty-printed or not, and the text format messages are one per line.
+    fn split_readable<'a>(
+        &self,
+        input: &'a str,
+        format: MessageFormat,
+    ) -> anyhow::Result<Vec<&'a str>> {
+        if self.framing == Framing::None {
+            return Ok(vec![input]);
+        }
+
+        match format {
+            MessageFormat::Json => {
+                let mut messages = Vec::new();
+                let mut stream =
+                    serde_json::Deserializer::from_str(input).into_iter::<serde::de::IgnoredAny>();
+                let mut start = 0;
+                while let Some(value) = stream.next() {
+                    value.map_err(|e| {
+                        anyhow::anyhow!("invalid JSON message #{}: {e}", messages.len() + 1)
+                    })?;
+                    messages.push(input[start..stream.byte_offset()].trim());
+                    start = stream.byte_offset();
+                }
+                Ok(messages)
+            }
+            MessageFormat::Text => input
+                .lines()
+                .enumerate()
+                .filter(|(_, x)| !x.trim().is_empty())
+                .map(|(i, x)| {
+                    if !is_balanced(x) {
+                        anyhow::bail!(
+                            "the text format message on line {} is not complete, \
+                             with framing each message must be on a single line",
+                            i + 1
+                        );
+                    }
+                    Ok(x)
+                })
+                .collect(),
+        }
+    }
+
+    fn join_readable(&self, lines: Vec<String>) -> Vec<u8> {
+        match self.framing {
+            Framing::None => lines.concat().into_bytes(),
+            _ => lines
+                .iter()
+                .map(|x| format!("{x}\n"))
+                .collect::<String>()
+                .into_bytes(),
+        }
+    }
+
+    fn write_output(&self, data: Vec<u8>) -> anyhow::Result<()> {
         if let Some(output) = &self.output {
             std::fs::write(output, data)?;
         } else {
@@ -82,3 +238,28 @@ impl Executable for JsonCommand {
         Ok(())
     }
 }
+
+/// Whether the brackets of a text format line are closed, ignoring the strings and the comments.
+fn is_balanced(line: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => break,
+            (None, '{' | '<' | '[') => depth += 1,
+            (None, '}' | '>' | ']') => depth -= 1,
+            _ => {}
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    depth == 0 && quote.is_none()
+}
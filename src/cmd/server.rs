@@ -1,22 +1,42 @@
-use std::{net::SocketAddr, path::PathBuf, time::Duration};
+use std::{path::PathBuf, pin::pin, time::Duration};
 
 use argh::FromArgs;
-use prost_reflect::DynamicMessage;
-use tonic::transport::Server;
+use futures_util::future::{Either, select};
+use prost_reflect::{DeserializeOptions, DynamicMessage};
+#[cfg(unix)]
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::{
+    Code,
+    codec::{BufferSettings, CompressionEncoding},
+    transport::Server,
+};
 
 use super::Executable;
 use crate::{
-    codec::DynamicProstCodec, descriptor_set::DescriptorSet, static_server::StaticService,
-    util::new_tokio_rt,
+    address::BindAddr,
+    codec::DynamicProstCodec,
+    connectrpc::ConnectService,
+    descriptor_set::DescriptorSet,
+    format::MessageFormat,
+    grpc_web::{CorsSettings, GrpcWebService},
+    health::{Health, HealthService, ServingStatus, parse_health},
+    shutdown::Shutdown,
+    static_server::{GrpcSettings, StaticService},
+    transcode::{Router, TranscodeService},
+    util::{
+        new_tokio_rt, parse_byte_size, parse_code, parse_compression_encoding, parse_duration,
+        parse_window_size, parse_worker_threads,
+    },
 };
 
 /// acting as a server to handle a gRPC method
 #[derive(FromArgs, Clone, Debug)]
 #[argh(subcommand, name = "server")]
 pub struct ServerCommand {
-    /// the target server address, it should be a socket address, e.g. `[::]:50051`.
+    /// the listening address, it should be a socket address, e.g. `[::]:50051`,
+    /// or a Unix domain socket, e.g. `unix:///tmp/grpc.sock` and `unix-abstract:grpc`.
     #[argh(option, short = 'b')]
-    bind_addr: SocketAddr,
+    bind_addr: BindAddr,
 
     /// the path to the grpc proto descriptor set file. could be generated by `protoc` or `compile` command of this tool.
     #[argh(option, short = 'D')]
@@ -30,13 +50,102 @@ pub struct ServerCommand {
     #[argh(positional)]
     method: String,
 
-    /// the request data in JSON format. Leave it empty to use the default value.
+    /// the request data in JSON or text format. Leave it empty to use the default value.
     #[argh(option, short = 'd')]
     data: Option<String>,
 
+    /// the format of the response data, could be `json` or `text`. The default is `json`.
+    #[argh(option, short = 'f', default = "MessageFormat::Json")]
+    format: MessageFormat,
+
     /// response stream cycle time, in seconds. This option is only valid for server streaming methods.
     #[argh(option)]
     stream_cycle: Option<u64>,
+
+    /// compress the response if the client accepts it, could be `gzip`, `zstd` or `deflate`.
+    /// compressed requests are always accepted.
+    #[argh(option, from_str_fn(parse_compression_encoding))]
+    compress: Option<CompressionEncoding>,
+
+    /// the maximum size of a request message, e.g. `16MiB`. The default is `4MiB`.
+    #[argh(option, from_str_fn(parse_byte_size))]
+    max_recv_size: Option<usize>,
+
+    /// the maximum size of a response message, e.g. `16MiB`. The default is unlimited.
+    #[argh(option, from_str_fn(parse_byte_size))]
+    max_send_size: Option<usize>,
+
+    /// the origin allowed to call the server with gRPC-Web from a browser, e.g. `http://localhost:3000`.
+    /// This option can be used multiple times. All origins are allowed if not set.
+    #[argh(option)]
+    cors_allow_origin: Vec<String>,
+
+    /// the request header allowed in CORS requests. This option can be used multiple times.
+    /// The headers requested by the browser are allowed if not set.
+    #[argh(option)]
+    cors_allow_header: Vec<String>,
+
+    /// how long the browser can cache the CORS preflight response, in seconds.
+    #[argh(option)]
+    cors_max_age: Option<u64>,
+
+    /// send HTTP/2 pings at the interval to keep the connections alive, e.g. `30s`,
+    /// so that the idle timeouts of the load balancers don't cut the long-lived streams.
+    #[argh(option, from_str_fn(parse_duration))]
+    keepalive_interval: Option<Duration>,
+
+    /// close the connection if a ping is not acknowledged in time, e.g. `10s`. The default is `20s`.
+    #[argh(option, from_str_fn(parse_duration))]
+    keepalive_timeout: Option<Duration>,
+
+    /// the initial HTTP/2 flow control window of a stream, e.g. `1MiB`.
+    #[argh(option, from_str_fn(parse_window_size))]
+    initial_stream_window_size: Option<u32>,
+
+    /// the initial HTTP/2 flow control window of a connection, e.g. `4MiB`.
+    #[argh(option, from_str_fn(parse_window_size))]
+    initial_connection_window_size: Option<u32>,
+
+    /// size the HTTP/2 flow control windows by the measured bandwidth, overriding the initial windows.
+    #[argh(switch)]
+    adaptive_window: bool,
+
+    /// disable `TCP_NODELAY` on the accepted connections, which means the small writes can be delayed to be coalesced.
+    #[argh(switch)]
+    disable_tcp_nodelay: bool,
+
+    /// enable the TCP keepalive probes after a connection is idle for the duration, e.g. `60s`.
+    #[argh(option, from_str_fn(parse_duration))]
+    tcp_keepalive: Option<Duration>,
+
+    /// how long the calls in flight can take to finish on SIGINT or SIGTERM, e.g. `30s`,
+    /// the connections are closed after that. The default is `10s`.
+    #[argh(
+        option,
+        from_str_fn(parse_duration),
+        default = "Duration::from_secs(10)"
+    )]
+    shutdown_grace: Duration,
+
+    /// the status code ending the server streams on shutdown, e.g. `unavailable` or `ok`.
+    /// The default is `unavailable`.
+    #[argh(option, from_str_fn(parse_code), default = "Code::Unavailable")]
+    shutdown_status: Code,
+
+    /// the status of a service reported by `grpc.health.v1.Health`, in format of `service=status`,
+    /// e.g. `helloworld.Greeter=not_serving`, the whole server has an empty service name.
+    /// This option can be used multiple times. The server and the served service are `serving` by default.
+    #[argh(option, from_str_fn(parse_health))]
+    health: Vec<(String, ServingStatus)>,
+
+    /// change the health statuses at runtime by writing the format of `health` to stdin, one per line.
+    #[argh(switch)]
+    health_from_stdin: bool,
+
+    /// run on a multi-threaded runtime with the number of worker threads.
+    /// The default is single-threaded.
+    #[argh(option, from_str_fn(parse_worker_threads))]
+    worker_threads: Option<usize>,
 }
 impl Executable for ServerCommand {
     fn run(&self) -> anyhow::Result<()> {
@@ -61,32 +170,141 @@ impl Executable for ServerCommand {
         let req_type = method.input();
         let resp_type = method.output();
 
+        let rt = new_tokio_rt(self.worker_threads);
+        let shutdown = {
+            let _rt = rt.enter();
+            Shutdown::on_signal()?
+        };
+
         let resp_msg = match &self.data {
-            Some(data) => {
-                let mut resp_msg_json_de = serde_json::de::Deserializer::from_str(data);
-                let msg = DynamicMessage::deserialize(resp_type.clone(), &mut resp_msg_json_de)?;
-                resp_msg_json_de.end()?;
-                msg
-            }
+            Some(data) => self
+                .format
+                .parse(resp_type.clone(), data, &DeserializeOptions::new())?,
             None => DynamicMessage::new(resp_type.clone()),
         };
 
+        let served_service = if self.disable_package_emission {
+            service.name()
+        } else {
+            service.full_name()
+        };
+        let health = Health::new(
+            [
+                (String::new(), ServingStatus::Serving),
+                (served_service.to_string(), ServingStatus::Serving),
+            ]
+            .into_iter()
+            .chain(self.health.iter().cloned()),
+        );
+        if self.health_from_stdin {
+            update_health_from_stdin(health.clone());
+        }
+
         let svc = StaticService::new(
             // yes, this is reversed.
-            DynamicProstCodec::new(resp_type, req_type),
-            if self.disable_package_emission {
-                service.name()
-            } else {
-                service.full_name()
-            },
+            DynamicProstCodec::new(resp_type, req_type, BufferSettings::default()),
+            served_service,
             method_name,
             method.clone(),
             resp_msg,
             self.stream_cycle.map(Duration::from_secs),
-        )?;
+            GrpcSettings {
+                send_compression: self.compress,
+                max_decoding_message_size: self.max_recv_size,
+                max_encoding_message_size: self.max_send_size,
+            },
+        )?
+        .with_shutdown(shutdown.clone(), self.shutdown_status);
+
+        // the REST routes of the `google.api.http` annotations are served as well
+        let path = svc.served_uri().clone();
+        let svc = HealthService::new(svc, health.clone()).with_shutdown(shutdown.clone());
+        let svc = TranscodeService::new(
+            ConnectService::new(svc, method.clone(), path.clone()),
+            Router::for_method(&method)?,
+            path,
+        );
 
-        new_tokio_rt()
-            .block_on(Server::builder().serve(self.bind_addr, svc))
-            .map_err(Into::into)
+        // gRPC-Web, Connect and REST are served over both HTTP/1.1 and HTTP/2
+        let svc = GrpcWebService::new(
+            svc,
+            CorsSettings {
+                allow_origins: self.cors_allow_origin.clone(),
+                allow_headers: self.cors_allow_header.clone(),
+                max_age: self.cors_max_age,
+            },
+        );
+
+        let server = Server::builder()
+            .accept_http1(true)
+            .http2_keepalive_interval(self.keepalive_interval)
+            .http2_keepalive_timeout(self.keepalive_timeout)
+            .initial_stream_window_size(self.initial_stream_window_size)
+            .initial_connection_window_size(self.initial_connection_window_size)
+            .http2_adaptive_window(self.adaptive_window.then_some(true))
+            .tcp_nodelay(!self.disable_tcp_nodelay)
+            .tcp_keepalive(self.tcp_keepalive);
+
+        let bind_addr = self.bind_addr.clone();
+        let grace = self.shutdown_grace;
+        let task = async move {
+            // no new connection or call is accepted once the shutdown begins,
+            // the clients are told to go away and the calls in flight can finish
+            let serve = async {
+                match bind_addr {
+                    BindAddr::Tcp(addr) => {
+                        server
+                            .serve_with_shutdown(addr, svc, shutdown.clone().wait())
+                            .await
+                    }
+                    #[cfg(unix)]
+                    BindAddr::Unix(addr) => {
+                        let incoming = UnixListenerStream::new(addr.bind()?);
+                        server
+                            .serve_with_incoming_shutdown(svc, incoming, shutdown.clone().wait())
+                            .await
+                    }
+                }?;
+                Ok::<_, anyhow::Error>(())
+            };
+            let grace_over = async {
+                shutdown.clone().wait().await;
+                health.set_all(ServingStatus::NotServing);
+                eprintln!("shutting down, waiting up to {grace:?} for the calls in flight");
+                tokio::time::sleep(grace).await;
+            };
+
+            match select(pin!(serve), pin!(grace_over)).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => {
+                    eprintln!("the grace period is over, closing the remaining connections");
+                    Ok(())
+                }
+            }
+        };
+
+        rt.block_on(task)
     }
 }
+
+/// Apply the health statuses written to stdin, in format of `service=status`.
+fn update_health_from_stdin(health: Health) {
+    // stdin is read in blocking mode, so it doesn't hold the runtime back
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_health(&line) {
+                Ok((service, status)) => {
+                    eprintln!("health of `{service}` set to {}", status.as_str_name());
+                    health.set(&service, status);
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    });
+}
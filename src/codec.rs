@@ -1,19 +1,48 @@
-use prost::Message;
-use prost_reflect::{DynamicMessage, MessageDescriptor};
+use prost::{
+    Message,
+    bytes::{Buf, BufMut, Bytes},
+};
+use prost_reflect::{DynamicMessage, MessageDescriptor, ReflectMessage};
 use tonic::{
     Status,
     codec::{BufferSettings, Codec, DecodeBuf, Decoder, EncodeBuf, Encoder},
 };
 
+use crate::validate::Validation;
+
 #[derive(Debug, Clone)]
 pub struct DynamicProstCodec {
     req: MessageDescriptor,
     resp: MessageDescriptor,
+    buffer_settings: BufferSettings,
+    validation: Validation,
 }
 
 impl DynamicProstCodec {
-    pub fn new(req: MessageDescriptor, resp: MessageDescriptor) -> Self {
-        Self { req, resp }
+    pub fn new(
+        req: MessageDescriptor,
+        resp: MessageDescriptor,
+        buffer_settings: BufferSettings,
+    ) -> Self {
+        Self {
+            req,
+            resp,
+            buffer_settings,
+            validation: Validation::default(),
+        }
+    }
+
+    /// Check the outgoing messages before sending them.
+    pub fn with_validation(mut self, validation: Validation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Run the same checks as the encoder. Errors raised inside the encoder of a client
+    /// only reset the HTTP/2 stream, call this before sending to get the actual reason.
+    #[allow(clippy::result_large_err)]
+    pub fn check(&self, item: &DynamicMessage) -> Result<(), Status> {
+        check_message(&self.req, self.validation, item)
     }
 }
 
@@ -26,23 +55,25 @@ impl Codec for DynamicProstCodec {
 
     fn encoder(&mut self) -> Self::Encoder {
         DynamicProstEncoder {
-            _req: self.req.clone(),
-            buffer_settings: BufferSettings::default(),
+            req: self.req.clone(),
+            buffer_settings: self.buffer_settings,
+            validation: self.validation,
         }
     }
 
     fn decoder(&mut self) -> Self::Decoder {
         DynamicProstDecoder {
             resp: self.resp.clone(),
-            buffer_settings: BufferSettings::default(),
+            buffer_settings: self.buffer_settings,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct DynamicProstEncoder {
-    _req: MessageDescriptor,
+    req: MessageDescriptor,
     buffer_settings: BufferSettings,
+    validation: Validation,
 }
 
 impl Encoder for DynamicProstEncoder {
@@ -50,10 +81,10 @@ impl Encoder for DynamicProstEncoder {
     type Error = Status;
 
     fn encode(&mut self, item: Self::Item, buf: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
-        item.encode(buf)
-            .expect("Message only errors if not enough space");
+        check_message(&self.req, self.validation, &item)?;
 
-        Ok(())
+        item.encode(buf)
+            .map_err(|e| Status::internal(e.to_string()))
     }
 
     fn buffer_settings(&self) -> BufferSettings {
@@ -84,6 +115,88 @@ impl Decoder for DynamicProstDecoder {
     }
 }
 
+/// Passes the already encoded payloads through without a schema, e.g. for forwarding and replaying.
+#[derive(Debug, Clone, Default)]
+pub struct RawBytesCodec {
+    buffer_settings: BufferSettings,
+}
+
+impl RawBytesCodec {
+    pub fn new(buffer_settings: BufferSettings) -> Self {
+        Self { buffer_settings }
+    }
+}
+
+impl Codec for RawBytesCodec {
+    type Encode = Bytes;
+    type Decode = Bytes;
+
+    type Encoder = RawBytesCodec;
+    type Decoder = RawBytesCodec;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        self.clone()
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        self.clone()
+    }
+}
+
+impl Encoder for RawBytesCodec {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, buf: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        buf.put(item);
+
+        Ok(())
+    }
+
+    fn buffer_settings(&self) -> BufferSettings {
+        self.buffer_settings
+    }
+}
+
+impl Decoder for RawBytesCodec {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn decode(&mut self, buf: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        Ok(Some(buf.copy_to_bytes(buf.remaining())))
+    }
+
+    fn buffer_settings(&self) -> BufferSettings {
+        self.buffer_settings
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn check_message(
+    desc: &MessageDescriptor,
+    validation: Validation,
+    item: &DynamicMessage,
+) -> Result<(), Status> {
+    if item.descriptor() != *desc {
+        return Err(Status::internal(format!(
+            "mismatched message type, expect `{}`, got `{}`",
+            desc.full_name(),
+            item.descriptor().full_name()
+        )));
+    }
+
+    let violations = validation.check(item);
+    if !violations.is_empty() {
+        return Err(Status::invalid_argument(format!(
+            "invalid `{}`: {}",
+            desc.full_name(),
+            violations.join("; ")
+        )));
+    }
+
+    Ok(())
+}
+
 fn from_decode_error(error: prost::DecodeError) -> Status {
     // Map Protobuf parse errors to an INTERNAL status code, as per
     // https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
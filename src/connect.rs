@@ -0,0 +1,414 @@
+//! Opening the client connection: TCP or Unix domain socket, optionally through a proxy and TLS.
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::{TryFutureExt, future::BoxFuture};
+use http::{HeaderValue, Uri, Version, header::HOST};
+use hyper_util::rt::TokioIo;
+use socket2::{SockRef, TcpKeepalive};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{
+        ClientConfig,
+        pki_types::{DnsName, ServerName},
+    },
+};
+use tonic::{
+    body::Body as TonicBody,
+    transport::{Channel, Endpoint},
+};
+use tower_service::Service;
+
+#[cfg(unix)]
+use crate::address::UnixAddr;
+use crate::{proxy::Proxy, tls::NullVerifier, util::lock};
+
+type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// A bidirectional byte stream, whatever the transport is.
+pub trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+pub type BoxIo = TokioIo<Box<dyn Io>>;
+
+/// How the client connections are made and kept alive, from the command line.
+#[derive(Clone, Debug)]
+pub struct TransportSettings {
+    pub proxy: Option<Proxy>,
+    pub http1: bool,
+    pub keepalive_interval: Option<Duration>,
+    pub keepalive_timeout: Option<Duration>,
+    pub keepalive_while_idle: bool,
+    pub initial_stream_window_size: Option<u32>,
+    pub initial_connection_window_size: Option<u32>,
+    pub adaptive_window: bool,
+    pub tcp_nodelay: bool,
+    pub tcp_keepalive: Option<Duration>,
+    pub plaintext: bool,
+}
+
+impl Default for TransportSettings {
+    fn default() -> Self {
+        TransportSettings {
+            proxy: None,
+            http1: false,
+            keepalive_interval: None,
+            keepalive_timeout: None,
+            keepalive_while_idle: false,
+            initial_stream_window_size: None,
+            initial_connection_window_size: None,
+            adaptive_window: false,
+            tcp_nodelay: true,
+            tcp_keepalive: None,
+            plaintext: false,
+        }
+    }
+}
+
+impl TransportSettings {
+    /// The HTTP/2 endpoint of the URI, the TCP settings are applied by the [`Connector`].
+    pub fn endpoint(&self, uri: &Uri) -> anyhow::Result<Endpoint> {
+        let mut endpoint = Endpoint::from_shared(uri.to_string())?
+            .http2_adaptive_window(self.adaptive_window)
+            .keep_alive_while_idle(self.keepalive_while_idle)
+            .initial_stream_window_size(self.initial_stream_window_size)
+            .initial_connection_window_size(self.initial_connection_window_size);
+        if let Some(interval) = self.keepalive_interval {
+            endpoint = endpoint.http2_keep_alive_interval(interval);
+        }
+        if let Some(timeout) = self.keepalive_timeout {
+            endpoint = endpoint.keep_alive_timeout(timeout);
+        }
+        Ok(endpoint)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Target {
+    #[cfg(unix)]
+    Unix(UnixAddr),
+    Tcp {
+        tls: bool,
+        proxy: Option<Proxy>,
+    },
+}
+
+/// Connect to the server address given on the command line, e.g. `http://`, `https://`, `unix:`.
+#[derive(Clone, Debug)]
+pub struct Connector {
+    target: Target,
+    alpn: &'static [u8],
+    tcp_nodelay: bool,
+    tcp_keepalive: Option<Duration>,
+    // the resolved address to connect to, instead of the host of the URI
+    addr: Option<SocketAddr>,
+}
+
+impl Connector {
+    /// Return the connector and the `http://` URI to send the requests to.
+    /// The proxy from the command line takes precedence over the environment variables.
+    pub fn new(server: &str, settings: &TransportSettings) -> anyhow::Result<(Self, Uri)> {
+        #[cfg(unix)]
+        if let Some(addr) = UnixAddr::parse(server) {
+            // the URI is only used for the `:authority` header
+            let connector = Connector {
+                target: Target::Unix(addr),
+                alpn: b"h2",
+                tcp_nodelay: settings.tcp_nodelay,
+                tcp_keepalive: settings.tcp_keepalive,
+                addr: None,
+            };
+            return Ok((connector, Uri::from_static("http://localhost")));
+        }
+        #[cfg(not(unix))]
+        crate::address::reject_unix(server)?;
+
+        let tls = server.starts_with("https://") || server.starts_with("grpcs://");
+        let uri = Uri::from_str(
+            &server
+                .replacen("https://", "http://", 1)
+                .replacen("grpcs://", "grpc://", 1),
+        )?;
+
+        let proxy = match &settings.proxy {
+            Some(proxy) => Some(proxy.clone()),
+            None => Proxy::from_env(uri.host().unwrap_or_default())?,
+        };
+
+        let connector = Connector {
+            target: Target::Tcp { tls, proxy },
+            alpn: b"h2",
+            tcp_nodelay: settings.tcp_nodelay,
+            tcp_keepalive: settings.tcp_keepalive,
+            addr: None,
+        };
+        Ok((connector, uri))
+    }
+
+    /// Connect to the address, the host of the URI is still used for the `:authority` and TLS.
+    pub fn resolved(mut self, addr: Option<SocketAddr>) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    /// Negotiate HTTP/1.1 instead of HTTP/2 with TLS.
+    pub fn http1(mut self) -> Self {
+        self.alpn = b"http/1.1";
+        self
+    }
+
+    pub async fn connect(&self, uri: Uri) -> io::Result<BoxIo> {
+        let (tls, proxy) = match &self.target {
+            #[cfg(unix)]
+            Target::Unix(addr) => return Ok(TokioIo::new(Box::new(addr.connect().await?))),
+            Target::Tcp { tls, proxy } => (*tls, proxy),
+        };
+
+        let host = uri
+            .host()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "host is missing"))?
+            .to_string();
+        let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+
+        let conn = match (proxy, self.addr) {
+            (Some(proxy), Some(addr)) => proxy.connect(&addr.ip().to_string(), addr.port()).await?,
+            (Some(proxy), None) => proxy.connect(&host, port).await?,
+            (None, Some(addr)) => TcpStream::connect(addr).await?,
+            (None, None) => TcpStream::connect((host.as_str(), port)).await?,
+        };
+        conn.set_nodelay(self.tcp_nodelay)?;
+        if let Some(time) = self.tcp_keepalive {
+            SockRef::from(&conn).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+
+        if !tls {
+            return Ok(TokioIo::new(Box::new(conn)));
+        }
+
+        let cfg = {
+            let mut c = ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NullVerifier))
+                .with_no_client_auth();
+            c.alpn_protocols = vec![self.alpn.to_vec()];
+            c
+        };
+
+        let domain = match IpAddr::from_str(host.trim_matches(['[', ']'])) {
+            Ok(ip) => ServerName::IpAddress(ip.into()),
+            Err(_) => ServerName::DnsName(
+                DnsName::try_from_str(&host)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+                    .to_owned(),
+            ),
+        };
+
+        let conn = TlsConnector::from(Arc::new(cfg))
+            .connect(domain, conn)
+            .await
+            .map_err(|e| io::Error::other(format!("TLS connection failed: {e}")))?;
+
+        Ok(TokioIo::new(Box::new(conn)))
+    }
+}
+
+impl Service<Uri> for Connector {
+    type Response = BoxIo;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<BoxIo>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connector = self.clone();
+        Box::pin(async move { connector.connect(uri).await })
+    }
+}
+
+/// A server to connect to, with the address resolved from a `dns:` target.
+#[derive(Clone, Debug)]
+pub struct Resolved {
+    pub server: String,
+    pub addr: Option<SocketAddr>,
+}
+
+impl Resolved {
+    /// The resolved address, or the server as given.
+    pub fn name(&self) -> String {
+        match self.addr {
+            Some(addr) => addr.to_string(),
+            None => self.server.clone(),
+        }
+    }
+}
+
+/// Expand a `dns:///host:port` target into all the resolved addresses, other targets are returned as is.
+/// The port defaults to 443 like other gRPC clients, and TLS is used unless `plaintext`.
+/// The host is kept for the `:authority` and TLS, only the connections go to the addresses.
+pub async fn resolve(server: &str, plaintext: bool) -> anyhow::Result<Vec<Resolved>> {
+    let Some(name) = server
+        .strip_prefix("dns:///")
+        .or_else(|| server.strip_prefix("dns:").filter(|x| !x.starts_with("//")))
+    else {
+        if server.starts_with("dns://") {
+            anyhow::bail!("custom DNS servers are not supported: {server}");
+        }
+        let server = server.to_string();
+        return Ok(vec![Resolved { server, addr: None }]);
+    };
+
+    // a bare IPv6 address has colons but no port, e.g. `fe80::1`
+    let (host, port) = match name.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+            let port = port
+                .parse::<u16>()
+                .map_err(|_| anyhow::anyhow!("invalid port in {server}"))?;
+            (host, port)
+        }
+        _ => (name, 443),
+    };
+    let host = host.trim_matches(['[', ']']);
+    if host.is_empty() {
+        anyhow::bail!("host is missing in {server}");
+    }
+
+    let scheme = if plaintext { "http" } else { "https" };
+    let uri = if host.contains(':') {
+        format!("{scheme}://[{host}]:{port}")
+    } else {
+        format!("{scheme}://{host}:{port}")
+    };
+    let addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| anyhow::Error::new(e).context(format!("failed to resolve {name}")))?
+        .map(|addr| Resolved {
+            server: uri.clone(),
+            addr: Some(addr),
+        })
+        .collect::<Vec<_>>();
+    if addrs.is_empty() {
+        anyhow::bail!("no address resolved for {name}");
+    }
+
+    Ok(addrs)
+}
+
+/// Send plain HTTP requests over HTTP/1.1 or HTTP/2, for the protocols translated from gRPC.
+/// The clones share the connections. A HTTP/1.1 connection only takes one request at a time,
+/// so more are opened when all of them are busy.
+#[derive(Clone)]
+pub enum HttpSender {
+    Http1(Arc<Http1Connections>),
+    Http2(Channel),
+}
+
+/// The idle HTTP/1.1 connections to a server, and how to open new ones.
+pub struct Http1Connections {
+    connector: Connector,
+    uri: Uri,
+    authority: HeaderValue,
+    idle: Mutex<Vec<hyper::client::conn::http1::SendRequest<TonicBody>>>,
+}
+
+impl Http1Connections {
+    async fn open(&self) -> anyhow::Result<hyper::client::conn::http1::SendRequest<TonicBody>> {
+        let io = self.connector.connect(self.uri.clone()).await?;
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(io).await?;
+        tokio::spawn(conn);
+        // a new connection only takes requests once its task is running
+        sender.ready().await?;
+        Ok(sender)
+    }
+
+    /// Take a connection done with its previous response, the closed ones are dropped.
+    fn take(&self) -> Option<hyper::client::conn::http1::SendRequest<TonicBody>> {
+        let mut idle = lock(&self.idle);
+        idle.retain(|x| !x.is_closed());
+        let i = idle.iter().position(|x| x.is_ready())?;
+        Some(idle.swap_remove(i))
+    }
+
+    /// Give back a connection once the response head is received, it is ready again
+    /// after the response body is read.
+    fn put(&self, sender: hyper::client::conn::http1::SendRequest<TonicBody>) {
+        lock(&self.idle).push(sender);
+    }
+}
+
+impl HttpSender {
+    pub async fn connect(
+        connector: Connector,
+        uri: Uri,
+        settings: &TransportSettings,
+    ) -> anyhow::Result<Self> {
+        if !settings.http1 {
+            let ch = settings
+                .endpoint(&uri)?
+                .connect_with_connector(connector)
+                .await?;
+            return Ok(HttpSender::Http2(ch));
+        }
+
+        let connections = Http1Connections {
+            connector: connector.http1(),
+            authority: HeaderValue::from_str(uri.authority().map_or("", |x| x.as_str()))?,
+            uri,
+            idle: Mutex::new(Vec::new()),
+        };
+        // fail early if the server is unreachable
+        let sender = connections.open().await?;
+        connections.put(sender);
+
+        Ok(HttpSender::Http1(Arc::new(connections)))
+    }
+}
+
+impl Service<http::Request<TonicBody>> for HttpSender {
+    type Response = http::Response<TonicBody>;
+    type Error = StdError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self {
+            // the connections are opened on demand
+            HttpSender::Http1(_) => Poll::Ready(Ok(())),
+            HttpSender::Http2(ch) => Service::poll_ready(ch, cx).map_err(Into::into),
+        }
+    }
+
+    fn call(&mut self, mut req: http::Request<TonicBody>) -> Self::Future {
+        match self {
+            HttpSender::Http1(connections) => {
+                let connections = connections.clone();
+                // tonic marks the requests as HTTP/2
+                *req.version_mut() = Version::HTTP_11;
+                req.headers_mut()
+                    .insert(HOST, connections.authority.clone());
+                Box::pin(async move {
+                    let mut sender = match connections.take() {
+                        Some(sender) => sender,
+                        None => connections.open().await?,
+                    };
+                    let resp = sender.send_request(req).await?;
+                    connections.put(sender);
+                    Ok(resp.map(TonicBody::new))
+                })
+            }
+            HttpSender::Http2(ch) => Box::pin(ch.call(req).map_err(Into::into)),
+        }
+    }
+}
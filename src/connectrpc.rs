@@ -0,0 +1,798 @@
+//! The [Connect protocol](https://connectrpc.com/docs/protocol), translated from and to gRPC.
+//!
+//! Unary calls send the bare message as the body and report errors as a JSON body with a HTTP
+//! error status. Streaming calls use the same envelopes as the gRPC frames, and end with a JSON
+//! end-stream message carrying the error and the trailers. The JSON messages are converted with
+//! the method descriptors.
+
+use std::{
+    io::Read,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+use futures_util::{future::BoxFuture, stream};
+use http::{
+    HeaderMap, HeaderName, HeaderValue, StatusCode, Uri,
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, TE},
+};
+use http_body::{Body, Frame, SizeHint};
+use http_body_util::{BodyExt, Full, StreamBody};
+use prost::{
+    Message,
+    bytes::{BufMut, Bytes, BytesMut},
+};
+use prost_reflect::{DeserializeOptions, DynamicMessage, MessageDescriptor, MethodDescriptor};
+use serde_json::json;
+use tonic::{Code, Status, body::Body as TonicBody, metadata::GRPC_CONTENT_TYPE};
+use tower_service::Service;
+
+use crate::connect::HttpSender;
+
+type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+pub const COMPRESSED_FLAG: u8 = 0x01;
+const END_STREAM_FLAG: u8 = 0x02;
+const FRAME_HEADER_SIZE: usize = 5;
+
+const GRPC_ENCODING: &str = "grpc-encoding";
+const GRPC_ACCEPT_ENCODING: &str = "grpc-accept-encoding";
+const GRPC_TIMEOUT: &str = "grpc-timeout";
+const CONNECT_CONTENT_ENCODING: &str = "connect-content-encoding";
+const CONNECT_ACCEPT_ENCODING: &str = "connect-accept-encoding";
+const CONNECT_PROTOCOL_VERSION: &str = "connect-protocol-version";
+const CONNECT_TIMEOUT_MS: &str = "connect-timeout-ms";
+/// The prefix of the trailers sent as headers in unary calls.
+const TRAILER_PREFIX: &str = "trailer-";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectCodec {
+    Proto,
+    Json,
+}
+
+impl ConnectCodec {
+    /// Detect the codec and whether the call is streaming from a `content-type`,
+    /// `None` if it's not Connect.
+    pub fn from_content_type(content_type: &str) -> Option<(Self, bool)> {
+        match content_type.split(';').next().unwrap_or_default().trim() {
+            "application/proto" => Some((ConnectCodec::Proto, false)),
+            "application/json" => Some((ConnectCodec::Json, false)),
+            "application/connect+proto" => Some((ConnectCodec::Proto, true)),
+            "application/connect+json" => Some((ConnectCodec::Json, true)),
+            _ => None,
+        }
+    }
+
+    fn content_type(self, streaming: bool) -> HeaderValue {
+        HeaderValue::from_static(match (self, streaming) {
+            (ConnectCodec::Proto, false) => "application/proto",
+            (ConnectCodec::Json, false) => "application/json",
+            (ConnectCodec::Proto, true) => "application/connect+proto",
+            (ConnectCodec::Json, true) => "application/connect+json",
+        })
+    }
+
+    /// Convert a protobuf binary message to this codec.
+    #[allow(clippy::result_large_err)]
+    fn encode(self, desc: &MessageDescriptor, data: Bytes) -> Result<Bytes, Status> {
+        match self {
+            ConnectCodec::Proto => Ok(data),
+            ConnectCodec::Json => {
+                let msg = DynamicMessage::decode(desc.clone(), data)
+                    .map_err(|e| Status::internal(format!("invalid message: {e}")))?;
+                let json = serde_json::to_vec(&msg)
+                    .map_err(|e| Status::internal(format!("failed to encode JSON: {e}")))?;
+                Ok(json.into())
+            }
+        }
+    }
+
+    /// Convert a message of this codec to protobuf binary.
+    #[allow(clippy::result_large_err)]
+    fn decode(self, desc: &MessageDescriptor, data: Bytes) -> Result<Bytes, Status> {
+        match self {
+            ConnectCodec::Proto => Ok(data),
+            ConnectCodec::Json => {
+                let mut de = serde_json::Deserializer::from_slice(&data);
+                let msg = DynamicMessage::deserialize_with_options(
+                    desc.clone(),
+                    &mut de,
+                    &DeserializeOptions::new(),
+                )
+                .and_then(|x| de.end().map(|_| x))
+                .map_err(|e| Status::invalid_argument(format!("invalid JSON message: {e}")))?;
+                Ok(msg.encode_to_vec().into())
+            }
+        }
+    }
+}
+
+/// A gRPC transport speaking Connect to the server, to be used with [`tonic::client::Grpc`].
+#[derive(Clone)]
+pub struct ConnectClient {
+    sender: HttpSender,
+    codec: ConnectCodec,
+    /// the method to call, required by the JSON codec. Calls are unary if not set.
+    method: Option<MethodDescriptor>,
+}
+
+impl ConnectClient {
+    pub fn new(
+        sender: HttpSender,
+        codec: ConnectCodec,
+        method: Option<MethodDescriptor>,
+    ) -> anyhow::Result<Self> {
+        if codec == ConnectCodec::Json && method.is_none() {
+            anyhow::bail!("the JSON codec of Connect requires the method descriptor");
+        }
+
+        Ok(Self {
+            sender,
+            codec,
+            method,
+        })
+    }
+}
+
+impl Service<http::Request<TonicBody>> for ConnectClient {
+    type Response = http::Response<TonicBody>;
+    type Error = StdError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.sender.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<TonicBody>) -> Self::Future {
+        let codec = self.codec;
+        let streaming = self
+            .method
+            .as_ref()
+            .is_some_and(|x| x.is_client_streaming() || x.is_server_streaming());
+        let input = self.method.as_ref().map(|x| x.input());
+        let output = self.method.as_ref().map(|x| x.output());
+
+        let (mut parts, body) = req.into_parts();
+        let headers = &mut parts.headers;
+        headers.remove(TE);
+        if let Some(timeout) = headers
+            .remove(GRPC_TIMEOUT)
+            .and_then(|x| grpc_timeout_ms(&x))
+        {
+            headers.insert(CONNECT_TIMEOUT_MS, HeaderValue::from(timeout));
+        }
+        headers.insert(CONTENT_TYPE, codec.content_type(streaming));
+        headers.insert(CONNECT_PROTOCOL_VERSION, HeaderValue::from_static("1"));
+
+        let encoding = headers.remove(GRPC_ENCODING);
+        let accept_encoding = headers.remove(GRPC_ACCEPT_ENCODING);
+        let (encoding_header, accept_encoding_header) = if streaming {
+            (
+                HeaderName::from_static(CONNECT_CONTENT_ENCODING),
+                HeaderName::from_static(CONNECT_ACCEPT_ENCODING),
+            )
+        } else {
+            (CONTENT_ENCODING, ACCEPT_ENCODING)
+        };
+        if let Some(x) = encoding {
+            headers.insert(encoding_header.clone(), x);
+        }
+        if let Some(x) = accept_encoding {
+            headers.insert(accept_encoding_header, x);
+        }
+
+        // the request messages are the same envelopes in streaming calls, bare in unary calls
+        #[allow(clippy::result_large_err)]
+        let body = Envelopes::new(body, move |item| match item {
+            Item::Message(flags, data) => {
+                let data = match &input {
+                    Some(input) if flags & COMPRESSED_FLAG == 0 => codec.encode(input, data)?,
+                    _ if codec == ConnectCodec::Json => {
+                        return Err(Status::unimplemented(
+                            "compression is not supported with the JSON codec",
+                        ));
+                    }
+                    _ => data,
+                };
+                let data = if streaming {
+                    envelope(flags, &data)
+                } else {
+                    data
+                };
+                Ok(Some(Frame::data(data)))
+            }
+            _ => Ok(None),
+        });
+        let resp = self
+            .sender
+            .call(http::Request::from_parts(parts, TonicBody::new(body)));
+
+        Box::pin(async move {
+            let resp = resp.await?;
+            if streaming {
+                Ok(streaming_response(resp, codec, output, encoding_header))
+            } else {
+                unary_response(resp, codec, output).await
+            }
+        })
+    }
+}
+
+/// Translate the Connect unary response to a gRPC response.
+async fn unary_response(
+    resp: http::Response<TonicBody>,
+    codec: ConnectCodec,
+    output: Option<MessageDescriptor>,
+) -> Result<http::Response<TonicBody>, StdError> {
+    let (mut parts, body) = resp.into_parts();
+    let data = body.collect().await?.to_bytes();
+
+    if parts.status != StatusCode::OK {
+        let fallback = code_from_http_status(parts.status);
+        let status = serde_json::from_slice(&data)
+            .map(|x| status_from_json(&x, fallback))
+            .unwrap_or_else(|_| Status::new(fallback, String::from_utf8_lossy(&data)));
+        return Ok(trailers_only(status));
+    }
+
+    // the trailers are sent as prefixed headers
+    let mut trailers = HeaderMap::new();
+    for (key, value) in &parts.headers {
+        if let Some(name) = key.as_str().strip_prefix(TRAILER_PREFIX)
+            && let Ok(name) = HeaderName::from_bytes(name.as_bytes())
+        {
+            trailers.append(name, value.clone());
+        }
+    }
+    Status::new(Code::Ok, "").add_header(&mut trailers)?;
+
+    let compressed = parts.headers.remove(CONTENT_ENCODING);
+    let frame = match (&compressed, &output) {
+        (None, Some(output)) => envelope(0, &codec.decode(output, data)?),
+        (None, None) => envelope(0, &data),
+        (Some(_), _) => envelope(COMPRESSED_FLAG, &data),
+    };
+    if let Some(x) = compressed {
+        parts.headers.insert(GRPC_ENCODING, x);
+    }
+    parts.headers.insert(CONTENT_TYPE, GRPC_CONTENT_TYPE);
+
+    let frames = [Frame::data(frame), Frame::trailers(trailers)];
+    let body = StreamBody::new(stream::iter(frames.map(Ok::<_, Status>)));
+    Ok(http::Response::from_parts(parts, TonicBody::new(body)))
+}
+
+/// Translate the Connect streaming response to a gRPC response, the end-stream message becomes the trailers.
+#[allow(clippy::result_large_err)]
+fn streaming_response(
+    resp: http::Response<TonicBody>,
+    codec: ConnectCodec,
+    output: Option<MessageDescriptor>,
+    encoding_header: HeaderName,
+) -> http::Response<TonicBody> {
+    let (mut parts, body) = resp.into_parts();
+    if parts.status != StatusCode::OK {
+        return trailers_only(Status::new(
+            code_from_http_status(parts.status),
+            format!("HTTP status {}", parts.status),
+        ));
+    }
+
+    let encoding = parts.headers.remove(&encoding_header);
+    if let Some(x) = &encoding {
+        parts.headers.insert(GRPC_ENCODING, x.clone());
+    }
+    let mut ended = false;
+    let body = Envelopes::new(body, move |item| match item {
+        Item::Message(flags, data) if flags & END_STREAM_FLAG != 0 => {
+            ended = true;
+            let data = if flags & COMPRESSED_FLAG != 0 {
+                decompress(encoding.as_ref(), &data)?
+            } else {
+                data
+            };
+            let end_stream = serde_json::from_slice(&data)
+                .map_err(|e| Status::internal(format!("invalid end-stream message: {e}")))?;
+            Ok(Some(Frame::trailers(end_stream_trailers(&end_stream)?)))
+        }
+        Item::Message(flags, data) => {
+            let data = match &output {
+                Some(output) if flags & COMPRESSED_FLAG == 0 => codec.decode(output, data)?,
+                _ => data,
+            };
+            Ok(Some(Frame::data(envelope(flags, &data))))
+        }
+        Item::Trailers(_) => Ok(None),
+        Item::End if ended => Ok(None),
+        Item::End => Err(Status::internal("missing end-stream message")),
+    });
+
+    parts.headers.insert(CONTENT_TYPE, GRPC_CONTENT_TYPE);
+    http::Response::from_parts(parts, TonicBody::new(body))
+}
+
+/// Serve Connect requests with a gRPC service of the method, other requests are passed through.
+/// Only the requests to the served path are translated, the other paths may belong to other services.
+#[derive(Clone, Debug)]
+pub struct ConnectService<S> {
+    inner: S,
+    method: MethodDescriptor,
+    path: Uri,
+}
+
+impl<S> ConnectService<S> {
+    pub fn new(inner: S, method: MethodDescriptor, path: Uri) -> Self {
+        Self {
+            inner,
+            method,
+            path,
+        }
+    }
+}
+
+impl<S> Service<http::Request<TonicBody>> for ConnectService<S>
+where
+    S: Service<http::Request<TonicBody>, Response = http::Response<TonicBody>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = http::Response<TonicBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<TonicBody>) -> Self::Future {
+        let detected = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(ConnectCodec::from_content_type)
+            .filter(|_| req.uri().path() == self.path.path());
+        let Some((codec, streaming)) = detected else {
+            return Box::pin(self.inner.call(req));
+        };
+
+        let input = self.method.input();
+        let output = self.method.output();
+
+        let (mut parts, body) = req.into_parts();
+        let headers = &mut parts.headers;
+        let encoding = if streaming {
+            headers.remove(CONNECT_CONTENT_ENCODING)
+        } else {
+            headers.remove(CONTENT_ENCODING)
+        };
+        // the responses are not compressed
+        for x in [CONNECT_ACCEPT_ENCODING, ACCEPT_ENCODING.as_str()] {
+            headers.remove(x);
+        }
+        if let Some(timeout) = headers
+            .remove(CONNECT_TIMEOUT_MS)
+            .and_then(|x| HeaderValue::from_str(&format!("{}m", x.to_str().ok()?)).ok())
+        {
+            headers.insert(GRPC_TIMEOUT, timeout);
+        }
+        headers.insert(CONTENT_TYPE, GRPC_CONTENT_TYPE);
+        headers.insert(TE, HeaderValue::from_static("trailers"));
+
+        if streaming {
+            #[allow(clippy::result_large_err)]
+            let body = Envelopes::new(body, move |item| match item {
+                Item::Message(flags, data) => {
+                    let data = if flags & COMPRESSED_FLAG != 0 {
+                        decompress(encoding.as_ref(), &data)?
+                    } else {
+                        data
+                    };
+                    Ok(Some(Frame::data(envelope(0, &codec.decode(&input, data)?))))
+                }
+                _ => Ok(None),
+            });
+            let resp = self
+                .inner
+                .call(http::Request::from_parts(parts, TonicBody::new(body)));
+
+            return Box::pin(
+                async move { Ok(serve_streaming_response(resp.await?, codec, output)) },
+            );
+        }
+
+        let mut inner = self.inner.clone();
+        Box::pin(async move {
+            let req = async {
+                let data = body.collect().await?.to_bytes();
+                let data = match &encoding {
+                    Some(_) => decompress(encoding.as_ref(), &data)?,
+                    None => data,
+                };
+                Ok::<_, Status>(envelope(0, &codec.decode(&input, data)?))
+            };
+            let frame = match req.await {
+                Ok(x) => x,
+                Err(status) => return Ok(connect_error(&status)),
+            };
+
+            let req = http::Request::from_parts(parts, TonicBody::new(Full::new(frame)));
+            let resp = inner.call(req).await?;
+
+            Ok(serve_unary_response(resp, codec, output)
+                .await
+                .unwrap_or_else(|status| connect_error(&status)))
+        })
+    }
+}
+
+/// Translate the gRPC response of a unary call to a Connect response.
+async fn serve_unary_response(
+    resp: http::Response<TonicBody>,
+    codec: ConnectCodec,
+    output: MessageDescriptor,
+) -> Result<http::Response<TonicBody>, Status> {
+    let (parts, body) = resp.into_parts();
+    if let Some(status) = Status::from_header_map(&parts.headers)
+        && status.code() != Code::Ok
+    {
+        return Err(status);
+    }
+
+    let collected = body.collect().await?;
+    let trailers = collected.trailers().cloned().unwrap_or_default();
+    if let Some(status) = Status::from_header_map(&trailers)
+        && status.code() != Code::Ok
+    {
+        return Err(status);
+    }
+
+    let data = collected.to_bytes();
+    let data = match split_envelope(&data) {
+        Some((_, payload)) => codec.encode(&output, payload)?,
+        None => return Err(Status::internal("missing response message")),
+    };
+
+    let mut resp = http::Response::new(TonicBody::new(Full::new(data)));
+    let headers = resp.headers_mut();
+    headers.insert(CONTENT_TYPE, codec.content_type(false));
+    for (key, value) in metadata(&parts.headers).chain(metadata(&trailers)) {
+        if let Ok(name) = HeaderName::from_bytes(format!("{TRAILER_PREFIX}{key}").as_bytes()) {
+            headers.append(name, value.clone());
+        }
+    }
+
+    Ok(resp)
+}
+
+/// Translate the gRPC response of a streaming call to a Connect response,
+/// the trailers become the end-stream message.
+#[allow(clippy::result_large_err)]
+fn serve_streaming_response(
+    resp: http::Response<TonicBody>,
+    codec: ConnectCodec,
+    output: MessageDescriptor,
+) -> http::Response<TonicBody> {
+    let (parts, body) = resp.into_parts();
+
+    // a trailers-only response has the status in the headers
+    let mut trailers_only = Status::from_header_map(&parts.headers).map(|_| parts.headers.clone());
+    let body = Envelopes::new(body, move |item| match item {
+        Item::Message(_, data) => Ok(Some(Frame::data(envelope(
+            0,
+            &codec.encode(&output, data)?,
+        )))),
+        Item::Trailers(trailers) => {
+            trailers_only = None;
+            Ok(Some(Frame::data(end_stream(&trailers))))
+        }
+        Item::End => Ok(trailers_only.take().map(|x| Frame::data(end_stream(&x)))),
+    });
+
+    let mut resp = http::Response::new(TonicBody::new(body));
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, codec.content_type(true));
+    resp
+}
+
+/// The end-stream message of the gRPC trailers.
+fn end_stream(trailers: &HeaderMap) -> Bytes {
+    let mut end_stream = serde_json::Map::new();
+
+    if let Some(status) = Status::from_header_map(trailers)
+        && status.code() != Code::Ok
+    {
+        end_stream.insert("error".to_string(), status_json(&status));
+    }
+
+    let mut values = serde_json::Map::new();
+    for (key, value) in metadata(trailers) {
+        let entry = values
+            .entry(key.as_str().to_string())
+            .or_insert_with(|| json!([]));
+        if let (serde_json::Value::Array(x), Ok(value)) = (entry, value.to_str()) {
+            x.push(value.into());
+        }
+    }
+    if !values.is_empty() {
+        end_stream.insert("metadata".to_string(), values.into());
+    }
+
+    let data = serde_json::to_vec(&end_stream).unwrap_or_default();
+    envelope(END_STREAM_FLAG, &data)
+}
+
+/// The gRPC trailers of the end-stream message.
+#[allow(clippy::result_large_err)]
+fn end_stream_trailers(end_stream: &serde_json::Value) -> Result<HeaderMap, Status> {
+    let mut trailers = HeaderMap::new();
+
+    if let Some(serde_json::Value::Object(values)) = end_stream.get("metadata") {
+        for (key, values) in values {
+            let (Ok(name), serde_json::Value::Array(values)) =
+                (HeaderName::from_bytes(key.as_bytes()), values)
+            else {
+                continue;
+            };
+            for value in values.iter().filter_map(|x| x.as_str()) {
+                if let Ok(value) = HeaderValue::from_str(value) {
+                    trailers.append(name.clone(), value);
+                }
+            }
+        }
+    }
+
+    let status = match end_stream.get("error") {
+        Some(error) => status_from_json(error, Code::Unknown),
+        None => Status::new(Code::Ok, ""),
+    };
+    status.add_header(&mut trailers)?;
+
+    Ok(trailers)
+}
+
+/// The custom metadata of gRPC headers or trailers.
+fn metadata(headers: &HeaderMap) -> impl Iterator<Item = (&HeaderName, &HeaderValue)> {
+    headers.iter().filter(|(key, _)| {
+        !key.as_str().starts_with("grpc-") && *key != CONTENT_TYPE && key.as_str() != "date"
+    })
+}
+
+/// A response carrying only the status in the headers.
+fn trailers_only(status: Status) -> http::Response<TonicBody> {
+    let mut resp = http::Response::new(TonicBody::empty());
+    let headers = resp.headers_mut();
+    headers.insert(CONTENT_TYPE, GRPC_CONTENT_TYPE);
+    if status.add_header(headers).is_err() {
+        headers.insert("grpc-status", (Code::Internal as i32).into());
+    }
+    resp
+}
+
+/// The Connect error response of a unary call.
+fn connect_error(status: &Status) -> http::Response<TonicBody> {
+    let body = serde_json::to_vec(&status_json(status)).unwrap_or_default();
+    let mut resp = http::Response::new(TonicBody::new(Full::new(Bytes::from(body))));
+    *resp.status_mut() = http_status(status.code());
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    resp
+}
+
+fn status_json(status: &Status) -> serde_json::Value {
+    let mut error = json!({ "code": code_name(status.code()) });
+    if !status.message().is_empty() {
+        error["message"] = status.message().into();
+    }
+    error
+}
+
+fn status_from_json(error: &serde_json::Value, fallback: Code) -> Status {
+    let code = error
+        .get("code")
+        .and_then(|x| x.as_str())
+        .map_or(fallback, code_from_name);
+    let message = error.get("message").and_then(|x| x.as_str()).unwrap_or("");
+    Status::new(code, message)
+}
+
+const CODE_NAMES: [(Code, &str); 16] = [
+    (Code::Cancelled, "canceled"),
+    (Code::Unknown, "unknown"),
+    (Code::InvalidArgument, "invalid_argument"),
+    (Code::DeadlineExceeded, "deadline_exceeded"),
+    (Code::NotFound, "not_found"),
+    (Code::AlreadyExists, "already_exists"),
+    (Code::PermissionDenied, "permission_denied"),
+    (Code::ResourceExhausted, "resource_exhausted"),
+    (Code::FailedPrecondition, "failed_precondition"),
+    (Code::Aborted, "aborted"),
+    (Code::OutOfRange, "out_of_range"),
+    (Code::Unimplemented, "unimplemented"),
+    (Code::Internal, "internal"),
+    (Code::Unavailable, "unavailable"),
+    (Code::DataLoss, "data_loss"),
+    (Code::Unauthenticated, "unauthenticated"),
+];
+
+fn code_name(code: Code) -> &'static str {
+    CODE_NAMES
+        .iter()
+        .find(|(x, _)| *x == code)
+        .map_or("unknown", |(_, name)| name)
+}
+
+fn code_from_name(name: &str) -> Code {
+    CODE_NAMES
+        .iter()
+        .find(|(_, x)| *x == name)
+        .map_or(Code::Unknown, |(code, _)| *code)
+}
+
+pub fn http_status(code: Code) -> StatusCode {
+    let status = match code {
+        Code::Cancelled => 499,
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => 400,
+        Code::DeadlineExceeded => 504,
+        Code::NotFound => 404,
+        Code::AlreadyExists | Code::Aborted => 409,
+        Code::PermissionDenied => 403,
+        Code::ResourceExhausted => 429,
+        Code::Unimplemented => 501,
+        Code::Unavailable => 503,
+        Code::Unauthenticated => 401,
+        _ => 500,
+    };
+    StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn code_from_http_status(status: StatusCode) -> Code {
+    match status.as_u16() {
+        400 => Code::Internal,
+        401 => Code::Unauthenticated,
+        403 => Code::PermissionDenied,
+        404 => Code::Unimplemented,
+        429 | 502 | 503 | 504 => Code::Unavailable,
+        _ => Code::Unknown,
+    }
+}
+
+/// The `grpc-timeout` value, e.g. `100m` or `5S`, in milliseconds rounded up.
+fn grpc_timeout_ms(value: &HeaderValue) -> Option<u64> {
+    let value = value.to_str().ok()?;
+    let (amount, unit) = value.split_at_checked(value.len().checked_sub(1)?)?;
+    let amount = amount.parse::<u128>().ok()?;
+    let nanos = match unit {
+        "H" => 3_600_000_000_000,
+        "M" => 60_000_000_000,
+        "S" => 1_000_000_000,
+        "m" => 1_000_000,
+        "u" => 1_000,
+        "n" => 1,
+        _ => return None,
+    };
+    u64::try_from((amount * nanos).div_ceil(1_000_000)).ok()
+}
+
+#[allow(clippy::result_large_err)]
+fn decompress(encoding: Option<&HeaderValue>, data: &[u8]) -> Result<Bytes, Status> {
+    let mut buf = Vec::new();
+    let result = match encoding.and_then(|x| x.to_str().ok()) {
+        Some("gzip") => GzDecoder::new(data).read_to_end(&mut buf),
+        Some("deflate") => ZlibDecoder::new(data).read_to_end(&mut buf),
+        Some("identity") => return Ok(Bytes::copy_from_slice(data)),
+        encoding => {
+            return Err(Status::unimplemented(format!(
+                "unsupported compression: {}",
+                encoding.unwrap_or_default()
+            )));
+        }
+    };
+    result.map_err(|e| Status::invalid_argument(format!("failed to decompress: {e}")))?;
+    Ok(buf.into())
+}
+
+pub fn envelope(flags: u8, data: &[u8]) -> Bytes {
+    let mut buf = BytesMut::with_capacity(FRAME_HEADER_SIZE + data.len());
+    buf.put_u8(flags);
+    buf.put_u32(data.len() as u32);
+    buf.put_slice(data);
+    buf.freeze()
+}
+
+/// Split the first envelope of the data into the flags and the payload.
+pub fn split_envelope(data: &Bytes) -> Option<(u8, Bytes)> {
+    let len = u32::from_be_bytes(data.get(1..FRAME_HEADER_SIZE)?.try_into().ok()?) as usize;
+    let payload = data.get(FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + len)?;
+    Some((data[0], data.slice_ref(payload)))
+}
+
+pub enum Item {
+    Message(u8, Bytes),
+    Trailers(HeaderMap),
+    End,
+}
+
+/// Map the envelopes (or gRPC frames) and the trailers of a body.
+pub struct Envelopes<F> {
+    inner: TonicBody,
+    buf: BytesMut,
+    done: bool,
+    map: F,
+}
+
+impl<F> Envelopes<F> {
+    pub fn new(inner: TonicBody, map: F) -> Self {
+        Self {
+            inner,
+            buf: BytesMut::new(),
+            done: false,
+            map,
+        }
+    }
+}
+
+impl<F> Body for Envelopes<F>
+where
+    F: FnMut(Item) -> Result<Option<Frame<Bytes>>, Status> + Unpin,
+{
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        loop {
+            if self.buf.len() >= FRAME_HEADER_SIZE {
+                let len = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]])
+                    as usize;
+                if self.buf.len() >= FRAME_HEADER_SIZE + len {
+                    let mut envelope = self.buf.split_to(FRAME_HEADER_SIZE + len).freeze();
+                    let flags = envelope[0];
+                    let data = envelope.split_off(FRAME_HEADER_SIZE);
+                    if let Some(frame) = (self.map)(Item::Message(flags, data))? {
+                        return Poll::Ready(Some(Ok(frame)));
+                    }
+                    continue;
+                }
+            }
+
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            let item = match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        self.buf.put(data);
+                        continue;
+                    }
+                    Err(frame) => Item::Trailers(frame.into_trailers().unwrap_or_default()),
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => {
+                    self.done = true;
+                    if !self.buf.is_empty() {
+                        return Poll::Ready(Some(Err(Status::internal(
+                            "incomplete envelope at the end of the body",
+                        ))));
+                    }
+                    Item::End
+                }
+            };
+
+            if let Some(frame) = (self.map)(item)? {
+                return Poll::Ready(Some(Ok(frame)));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::default()
+    }
+}
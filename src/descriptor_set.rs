@@ -1,29 +1,37 @@
 use std::{fs, io, path::Path};
 
-use prost::Message;
-use prost_reflect::{DescriptorPool, prost_types::FileDescriptorSet};
+use prost_reflect::DescriptorPool;
+use protox::Compiler;
 
+/// The pool is encoded and decoded as is, so the custom options (extensions of the
+/// `google.protobuf.*Options` messages) are preserved.
 pub struct DescriptorSet {
-    file_descriptor_set: FileDescriptorSet,
     descriptor_pool: DescriptorPool,
 }
 
 impl DescriptorSet {
     pub fn from_file(p: &Path) -> io::Result<Self> {
-        let raw_file_descriptor_set = fs::read(p)?;
+        Self::from_files([p])
+    }
+
+    /// Merge multiple descriptor set files into one, files with the same name are only added once.
+    /// The dependencies of a file should be in the same or a previous descriptor set.
+    pub fn from_files(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> io::Result<Self> {
+        let mut descriptor_pool = DescriptorPool::new();
 
-        let file_descriptor_set = FileDescriptorSet::decode(raw_file_descriptor_set.as_slice())?;
-        let descriptor_pool = DescriptorPool::from_file_descriptor_set(file_descriptor_set.clone())
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        for p in paths {
+            let raw_file_descriptor_set = fs::read(p)?;
 
-        Ok(Self {
-            file_descriptor_set,
-            descriptor_pool,
-        })
+            descriptor_pool
+                .decode_file_descriptor_set(raw_file_descriptor_set.as_slice())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+
+        Ok(Self { descriptor_pool })
     }
 
     pub fn to_file(&self, p: &Path) -> io::Result<()> {
-        let desc = self.file_descriptor_set.encode_to_vec();
+        let desc = self.descriptor_pool.encode_to_vec();
 
         fs::write(p, desc)?;
 
@@ -34,14 +42,13 @@ impl DescriptorSet {
         files: impl IntoIterator<Item = impl AsRef<Path>>,
         includes: impl IntoIterator<Item = impl AsRef<Path>>,
     ) -> anyhow::Result<Self> {
-        let file_descriptor_set = protox::compile(files, includes)?;
-        let descriptor_pool = DescriptorPool::from_file_descriptor_set(file_descriptor_set.clone())
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-
-        Ok(Self {
-            file_descriptor_set,
-            descriptor_pool,
-        })
+        let descriptor_pool = Compiler::new(includes)?
+            .include_source_info(true)
+            .include_imports(true)
+            .open_files(files)?
+            .descriptor_pool();
+
+        Ok(Self { descriptor_pool })
     }
 
     pub fn pool(&self) -> DescriptorPool {
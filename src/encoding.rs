@@ -0,0 +1,283 @@
+use std::str::FromStr;
+
+use base64::{
+    Engine, alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE},
+};
+
+const BASE64_STANDARD_LENIENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+const BASE64_URL_SAFE_LENIENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The textual encoding of protobuf binary data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BinaryEncoding {
+    #[default]
+    Raw,
+    Base64,
+    Base64Url,
+    Hex,
+    CEscape,
+}
+
+impl FromStr for BinaryEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(BinaryEncoding::Raw),
+            "base64" => Ok(BinaryEncoding::Base64),
+            "base64url" => Ok(BinaryEncoding::Base64Url),
+            "hex" => Ok(BinaryEncoding::Hex),
+            "c-escape" => Ok(BinaryEncoding::CEscape),
+            _ => Err(anyhow::anyhow!(
+                "unknown binary encoding, expect `raw`, `base64`, `base64url`, `hex` or `c-escape`"
+            )),
+        }
+    }
+}
+
+/// The encoding of the binary input, `auto` guesses it from the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputEncoding {
+    Auto,
+    Fixed(BinaryEncoding),
+}
+
+impl Default for InputEncoding {
+    fn default() -> Self {
+        InputEncoding::Fixed(BinaryEncoding::Raw)
+    }
+}
+
+impl FromStr for InputEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(InputEncoding::Auto),
+            _ => s.parse().map(InputEncoding::Fixed).map_err(|_| {
+                anyhow::anyhow!(
+                    "unknown binary encoding, expect `raw`, `base64`, `base64url`, `hex`, `c-escape` or `auto`"
+                )
+            }),
+        }
+    }
+}
+
+impl InputEncoding {
+    pub fn decode(self, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            InputEncoding::Auto => BinaryEncoding::detect(input).decode(input),
+            InputEncoding::Fixed(encoding) => encoding.decode(input),
+        }
+    }
+}
+
+impl BinaryEncoding {
+    /// Guess the encoding of the input.
+    ///
+    /// The guess is ambiguous by nature, e.g. `deadbeef` is both valid hex and base64,
+    /// hex wins in this case. Set the encoding explicitly if the guess is wrong.
+    pub fn detect(input: &[u8]) -> Self {
+        let Ok(text) = std::str::from_utf8(input) else {
+            return BinaryEncoding::Raw;
+        };
+        let text = text.trim();
+
+        if text.is_empty() || text.chars().any(|c| c.is_control() && !c.is_whitespace()) {
+            return BinaryEncoding::Raw;
+        }
+
+        let compact = text
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect::<String>();
+        let is_base64_padding = |s: &str| s.trim_end_matches('=').len() + 2 >= s.len();
+
+        if strip_hex_prefix(&compact)
+            .is_some_and(|s| s.len().is_multiple_of(2) && s.bytes().all(|b| b.is_ascii_hexdigit()))
+        {
+            BinaryEncoding::Hex
+        } else if compact.contains('\\') {
+            BinaryEncoding::CEscape
+        } else if is_base64_padding(&compact)
+            && compact
+                .trim_end_matches('=')
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/')
+        {
+            BinaryEncoding::Base64
+        } else if is_base64_padding(&compact)
+            && compact
+                .trim_end_matches('=')
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            BinaryEncoding::Base64Url
+        } else {
+            BinaryEncoding::Raw
+        }
+    }
+
+    pub fn decode(self, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let compact = || {
+            input
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect::<Vec<_>>()
+        };
+
+        match self {
+            BinaryEncoding::Raw => Ok(input.to_vec()),
+            BinaryEncoding::Base64 => Ok(BASE64_STANDARD_LENIENT.decode(compact())?),
+            BinaryEncoding::Base64Url => Ok(BASE64_URL_SAFE_LENIENT.decode(compact())?),
+            BinaryEncoding::Hex => {
+                let compact = String::from_utf8(compact())?;
+                let hex = strip_hex_prefix(&compact)
+                    .ok_or_else(|| anyhow::anyhow!("invalid hex string"))?;
+                decode_hex(hex)
+            }
+            BinaryEncoding::CEscape => c_unescape(std::str::from_utf8(input)?.trim()),
+        }
+    }
+
+    pub fn encode(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            BinaryEncoding::Raw => data.to_vec(),
+            BinaryEncoding::Base64 => BASE64_STANDARD.encode(data).into_bytes(),
+            BinaryEncoding::Base64Url => BASE64_URL_SAFE.encode(data).into_bytes(),
+            BinaryEncoding::Hex => data
+                .iter()
+                .flat_map(|b| {
+                    let digits = b"0123456789abcdef";
+                    [digits[usize::from(b >> 4)], digits[usize::from(b & 0x0f)]]
+                })
+                .collect(),
+            BinaryEncoding::CEscape => c_escape(data).into_bytes(),
+        }
+    }
+}
+
+/// Strip the `0x` prefix, or the `\x` prefix used by the PostgreSQL `bytea` output.
+fn strip_hex_prefix(s: &str) -> Option<&str> {
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("\\x"))
+        .unwrap_or(s);
+
+    (!s.is_empty()).then_some(s)
+}
+
+fn decode_hex(s: &str) -> anyhow::Result<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        anyhow::bail!("invalid hex string: odd length");
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..i + 2)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .ok_or_else(|| anyhow::anyhow!("invalid hex string at offset {i}"))
+        })
+        .collect()
+}
+
+/// Escape the bytes the same way as `CEscape` of the protobuf C++ library.
+fn c_escape(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len());
+
+    for &b in data {
+        match b {
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            b'\t' => s.push_str("\\t"),
+            b'"' => s.push_str("\\\""),
+            b'\'' => s.push_str("\\'"),
+            b'\\' => s.push_str("\\\\"),
+            0x20..=0x7e => s.push(char::from(b)),
+            _ => s.push_str(&format!("\\{b:03o}")),
+        }
+    }
+
+    s
+}
+
+/// Unescape a C-style escaped string, the surrounding quotes are optional.
+fn c_unescape(s: &str) -> anyhow::Result<Vec<u8>> {
+    let s = s
+        .strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .or_else(|| s.strip_prefix('\'').and_then(|x| x.strip_suffix('\'')))
+        .unwrap_or(s);
+
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes().peekable();
+
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+
+        let escaped = bytes
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("invalid escape sequence at the end of input"))?;
+
+        let v = match escaped {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'f' => 0x0c,
+            b'v' => 0x0b,
+            b'\\' | b'"' | b'\'' | b'?' => escaped,
+            b'0'..=b'7' => {
+                let mut v = u32::from(escaped - b'0');
+                for _ in 0..2 {
+                    match bytes.peek() {
+                        Some(d @ b'0'..=b'7') => {
+                            v = v * 8 + u32::from(d - b'0');
+                            bytes.next();
+                        }
+                        _ => break,
+                    }
+                }
+                u8::try_from(v).map_err(|_| anyhow::anyhow!("octal escape out of range: {v:o}"))?
+            }
+            b'x' => {
+                let mut v = 0u8;
+                let mut digits = 0;
+                while digits < 2 {
+                    match bytes.peek().and_then(|d| char::from(*d).to_digit(16)) {
+                        Some(d) => {
+                            v = v * 16 + d as u8;
+                            digits += 1;
+                            bytes.next();
+                        }
+                        None => break,
+                    }
+                }
+                if digits == 0 {
+                    anyhow::bail!("invalid hex escape sequence");
+                }
+                v
+            }
+            _ => anyhow::bail!("unknown escape sequence: \\{}", char::from(escaped)),
+        };
+
+        out.push(v);
+    }
+
+    Ok(out)
+}
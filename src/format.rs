@@ -0,0 +1,87 @@
+use std::{path::Path, str::FromStr};
+
+use prost_reflect::{
+    DeserializeOptions, DynamicMessage, MessageDescriptor, text_format::FormatOptions,
+};
+
+use crate::{
+    any::{ensure_type_urls_resolved, unresolved_json_type_urls, unresolved_type_urls},
+    unknown::render_unknown_fields,
+};
+
+/// The human readable representation of a protobuf message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageFormat {
+    #[default]
+    Json,
+    Text,
+}
+
+impl FromStr for MessageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(MessageFormat::Json),
+            "text" | "textproto" => Ok(MessageFormat::Text),
+            _ => Err(anyhow::anyhow!(
+                "unknown message format, expect `json` or `text`"
+            )),
+        }
+    }
+}
+
+impl MessageFormat {
+    /// Guess the format from the file extension, `None` if the extension is not recognized.
+    pub fn from_path(p: &Path) -> Option<Self> {
+        match p.extension()?.to_str()? {
+            "json" => Some(MessageFormat::Json),
+            "textproto" | "txtpb" | "pbtxt" | "prototxt" => Some(MessageFormat::Text),
+            _ => None,
+        }
+    }
+
+    pub fn parse(
+        self,
+        desc: MessageDescriptor,
+        input: &str,
+        options: &DeserializeOptions,
+    ) -> anyhow::Result<DynamicMessage> {
+        match self {
+            MessageFormat::Json => {
+                let pool = desc.parent_pool().clone();
+                let mut de = serde_json::de::Deserializer::from_str(input);
+                let msg = DynamicMessage::deserialize_with_options(desc, &mut de, options)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|msg| Ok(de.end().map(|_| msg)?));
+
+                // give a clear error if the failure is caused by unknown `Any` types
+                if msg.is_err()
+                    && let Ok(value) = serde_json::from_str(input)
+                {
+                    ensure_type_urls_resolved(unresolved_json_type_urls(&pool, &value))?;
+                }
+
+                msg
+            }
+            MessageFormat::Text => Ok(DynamicMessage::parse_text_format(desc, input)?),
+        }
+    }
+
+    /// Print the message, the unknown fields are included if `show_unknown_fields` is set.
+    pub fn print(self, msg: &DynamicMessage, show_unknown_fields: bool) -> anyhow::Result<String> {
+        ensure_type_urls_resolved(unresolved_type_urls(msg))?;
+
+        match self {
+            MessageFormat::Json if show_unknown_fields => {
+                let mut value = serde_json::to_value(msg)?;
+                render_unknown_fields(msg, &mut value);
+                Ok(serde_json::to_string(&value)?)
+            }
+            MessageFormat::Json => Ok(serde_json::to_string(msg)?),
+            MessageFormat::Text => Ok(msg.to_text_format_with_options(
+                &FormatOptions::new().skip_unknown_fields(!show_unknown_fields),
+            )),
+        }
+    }
+}
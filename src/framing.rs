@@ -0,0 +1,99 @@
+use std::{io::Read, str::FromStr};
+
+use flate2::read::GzDecoder;
+use prost::encoding::{decode_varint, encode_varint};
+
+use crate::raw::take;
+
+/// How a sequence of protobuf messages is laid out in a single binary stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// a single message without any framing
+    #[default]
+    None,
+    /// each message is prefixed with its length in varint
+    Delimited,
+    /// each message is prefixed with the 1-byte compressed flag and 4-byte big-endian length
+    Grpc,
+}
+
+impl FromStr for Framing {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Framing::None),
+            "delimited" => Ok(Framing::Delimited),
+            "grpc" => Ok(Framing::Grpc),
+            _ => Err(anyhow::anyhow!(
+                "unknown framing, expect `none`, `delimited` or `grpc`"
+            )),
+        }
+    }
+}
+
+impl Framing {
+    /// Split the stream into messages. Compressed gRPC frames are assumed to be gzip.
+    pub fn split(self, mut buf: &[u8]) -> anyhow::Result<Vec<Vec<u8>>> {
+        let mut messages = Vec::new();
+
+        match self {
+            Framing::None => messages.push(buf.to_vec()),
+            Framing::Delimited => {
+                while !buf.is_empty() {
+                    let len = usize::try_from(decode_varint(&mut buf)?)?;
+                    messages.push(take(&mut buf, len)?.to_vec());
+                }
+            }
+            Framing::Grpc => {
+                while !buf.is_empty() {
+                    let header = take(&mut buf, 5)?;
+                    let len = u32::from_be_bytes(header[1..].try_into().unwrap());
+                    let payload = take(&mut buf, len as usize)?;
+
+                    let message = match header[0] {
+                        0 => payload.to_vec(),
+                        1 => {
+                            let mut message = Vec::new();
+                            GzDecoder::new(payload).read_to_end(&mut message)?;
+                            message
+                        }
+                        flag => anyhow::bail!("invalid gRPC frame compressed flag: {flag}"),
+                    };
+                    messages.push(message);
+                }
+            }
+        }
+
+        Ok(messages)
+    }
+
+    /// Join the messages into a stream, gRPC frames are written uncompressed.
+    pub fn join(self, messages: impl IntoIterator<Item = Vec<u8>>) -> anyhow::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+
+        for (i, message) in messages.into_iter().enumerate() {
+            if self == Framing::None && i > 0 {
+                anyhow::bail!("multiple messages require a framing");
+            }
+            self.write_frame(&message, &mut buf)?;
+        }
+
+        Ok(buf)
+    }
+
+    /// Append a single framed message to the buffer.
+    pub fn write_frame(self, message: &[u8], buf: &mut Vec<u8>) -> anyhow::Result<()> {
+        match self {
+            Framing::None => {}
+            Framing::Delimited => encode_varint(message.len() as u64, buf),
+            Framing::Grpc => {
+                buf.push(0);
+                buf.extend_from_slice(&u32::try_from(message.len())?.to_be_bytes());
+            }
+        }
+        buf.extend_from_slice(message);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,489 @@
+//! [gRPC-Web](https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-WEB.md) translation.
+//!
+//! The messages are framed as in gRPC, the trailers are sent in a last frame with the `0x80` flag,
+//! and in the `-text` variant the whole body is base64 encoded.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use futures_util::future::BoxFuture;
+use http::{
+    HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
+    header::{
+        ACCEPT, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+        ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE,
+        ACCESS_CONTROL_REQUEST_HEADERS, CONTENT_TYPE, ORIGIN, TE, VARY,
+    },
+};
+use http_body::{Body, Frame, SizeHint};
+use prost::bytes::{Buf, BufMut, Bytes, BytesMut};
+use tonic::{Status, body::Body as TonicBody, metadata::GRPC_CONTENT_TYPE};
+use tower_service::Service;
+
+use crate::connect::HttpSender;
+
+type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+pub const GRPC_WEB_CONTENT_TYPE: &str = "application/grpc-web+proto";
+pub const GRPC_WEB_TEXT_CONTENT_TYPE: &str = "application/grpc-web-text+proto";
+
+/// The flag of the frame carrying the trailers.
+const TRAILERS_FLAG: u8 = 0x80;
+const FRAME_HEADER_SIZE: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebEncoding {
+    Binary,
+    /// base64 encoded body
+    Text,
+}
+
+impl WebEncoding {
+    /// Detect the encoding from a `content-type`, `None` if it's not gRPC-Web.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        if content_type.starts_with("application/grpc-web-text") {
+            Some(WebEncoding::Text)
+        } else if content_type.starts_with("application/grpc-web") {
+            Some(WebEncoding::Binary)
+        } else {
+            None
+        }
+    }
+
+    pub fn content_type(self) -> HeaderValue {
+        match self {
+            WebEncoding::Binary => HeaderValue::from_static(GRPC_WEB_CONTENT_TYPE),
+            WebEncoding::Text => HeaderValue::from_static(GRPC_WEB_TEXT_CONTENT_TYPE),
+        }
+    }
+
+    /// Encode the body to this encoding, the trailers of the body are sent in a trailers frame.
+    pub fn encode_body(self, body: TonicBody) -> TonicBody {
+        TonicBody::new(EncodeBody {
+            inner: body,
+            encoding: self,
+            rest: BytesMut::new(),
+            done: false,
+        })
+    }
+
+    /// Decode the body from this encoding, the trailers frame becomes the trailers of the body.
+    pub fn decode_body(self, body: TonicBody) -> TonicBody {
+        TonicBody::new(DecodeBody {
+            inner: body,
+            encoding: self,
+            text: BytesMut::new(),
+            buf: BytesMut::new(),
+            done: false,
+        })
+    }
+}
+
+/// A gRPC transport speaking gRPC-Web to the server, to be used with [`tonic::client::Grpc`].
+#[derive(Clone)]
+pub struct GrpcWebClient {
+    sender: HttpSender,
+    encoding: WebEncoding,
+}
+
+impl GrpcWebClient {
+    pub fn new(sender: HttpSender, encoding: WebEncoding) -> Self {
+        Self { sender, encoding }
+    }
+}
+
+impl Service<http::Request<TonicBody>> for GrpcWebClient {
+    type Response = http::Response<TonicBody>;
+    type Error = StdError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.sender.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<TonicBody>) -> Self::Future {
+        let encoding = self.encoding;
+        let (mut parts, body) = req.into_parts();
+
+        parts.headers.insert(CONTENT_TYPE, encoding.content_type());
+        parts.headers.insert(ACCEPT, encoding.content_type());
+        parts
+            .headers
+            .insert("x-grpc-web", HeaderValue::from_static("1"));
+        // the trailers are in the body
+        parts.headers.remove(TE);
+
+        let req = http::Request::from_parts(parts, encoding.encode_body(body));
+
+        let resp = self.sender.call(req);
+
+        Box::pin(async move {
+            let (mut parts, body) = resp.await?.into_parts();
+            if parts
+                .headers
+                .get(CONTENT_TYPE)
+                .and_then(|x| x.to_str().ok())
+                .and_then(WebEncoding::from_content_type)
+                .is_some()
+            {
+                parts.headers.insert(CONTENT_TYPE, GRPC_CONTENT_TYPE);
+            }
+
+            Ok(http::Response::from_parts(
+                parts,
+                encoding.decode_body(body),
+            ))
+        })
+    }
+}
+
+/// Move the trailers into a trailers frame, and base64 encode the body in `text` encoding.
+struct EncodeBody {
+    inner: TonicBody,
+    encoding: WebEncoding,
+    /// the bytes not encoded yet, base64 is encoded by 3 bytes
+    rest: BytesMut,
+    done: bool,
+}
+
+impl EncodeBody {
+    fn encode(&mut self, data: Bytes, end: bool) -> Option<Bytes> {
+        match self.encoding {
+            WebEncoding::Binary => (!data.is_empty()).then_some(data),
+            WebEncoding::Text => {
+                self.rest.put(data);
+                let n = if end {
+                    self.rest.len()
+                } else {
+                    self.rest.len() / 3 * 3
+                };
+                (n > 0).then(|| Bytes::from(BASE64_STANDARD.encode(self.rest.split_to(n))))
+            }
+        }
+    }
+}
+
+impl Body for EncodeBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        loop {
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            let data = match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => self.encode(data, false),
+                    Err(frame) => {
+                        let trailers = frame.into_trailers().unwrap_or_default();
+                        let data = encode_trailers(&trailers);
+                        self.encode(data, false)
+                    }
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => {
+                    self.done = true;
+                    self.encode(Bytes::new(), true)
+                }
+            };
+
+            if let Some(data) = data {
+                return Poll::Ready(Some(Ok(Frame::data(data))));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::default()
+    }
+}
+
+/// Decode the base64 body in `text` encoding, and turn the trailers frame into trailers.
+struct DecodeBody {
+    inner: TonicBody,
+    encoding: WebEncoding,
+    /// the base64 text not decoded yet, base64 is decoded by 4 characters
+    text: BytesMut,
+    /// the bytes not yet split into frames
+    buf: BytesMut,
+    done: bool,
+}
+
+impl DecodeBody {
+    #[allow(clippy::result_large_err)]
+    fn push(&mut self, data: Bytes) -> Result<(), Status> {
+        match self.encoding {
+            WebEncoding::Binary => self.buf.put(data),
+            WebEncoding::Text => {
+                self.text
+                    .extend(data.into_iter().filter(|x| !x.is_ascii_whitespace()));
+                let text = self.text.split_to(self.text.len() / 4 * 4);
+
+                // chunks may be encoded separately, so there might be padding in the middle
+                let mut start = 0;
+                for (i, group) in text.chunks(4).enumerate() {
+                    let end = (i + 1) * 4;
+                    if group.contains(&b'=') || end == text.len() {
+                        let decoded = BASE64_STANDARD
+                            .decode(&text[start..end])
+                            .map_err(|e| Status::internal(format!("invalid base64 body: {e}")))?;
+                        self.buf.put(decoded.as_slice());
+                        start = end;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn next_frame(&mut self) -> Result<Option<Frame<Bytes>>, Status> {
+        if self.buf.len() < FRAME_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let flag = self.buf[0];
+        let len = u32::from_be_bytes([self.buf[1], self.buf[2], self.buf[3], self.buf[4]]) as usize;
+        if self.buf.len() < FRAME_HEADER_SIZE + len {
+            return Ok(None);
+        }
+
+        let mut frame = self.buf.split_to(FRAME_HEADER_SIZE + len).freeze();
+        if flag & TRAILERS_FLAG == 0 {
+            return Ok(Some(Frame::data(frame)));
+        }
+
+        frame.advance(FRAME_HEADER_SIZE);
+        Ok(Some(Frame::trailers(decode_trailers(&frame)?)))
+    }
+}
+
+impl Body for DecodeBody {
+    type Data = Bytes;
+    type Error = Status;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        loop {
+            if let Some(frame) = self.next_frame()? {
+                return Poll::Ready(Some(Ok(frame)));
+            }
+
+            if self.done {
+                if !self.buf.is_empty() || !self.text.is_empty() {
+                    self.buf.clear();
+                    self.text.clear();
+                    return Poll::Ready(Some(Err(Status::internal(
+                        "incomplete gRPC-Web frame at the end of the body",
+                    ))));
+                }
+                return Poll::Ready(None);
+            }
+
+            match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => self.push(data)?,
+                    // trailers of the underlying HTTP/2 stream, if any
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => self.done = true,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::default()
+    }
+}
+
+/// Encode the trailers as a HTTP/1 header block in a trailers frame.
+fn encode_trailers(trailers: &HeaderMap) -> Bytes {
+    let mut block = Vec::new();
+    for (key, value) in trailers {
+        block.extend_from_slice(key.as_str().as_bytes());
+        block.extend_from_slice(b": ");
+        block.extend_from_slice(value.as_bytes());
+        block.extend_from_slice(b"\r\n");
+    }
+
+    let mut frame = BytesMut::with_capacity(FRAME_HEADER_SIZE + block.len());
+    frame.put_u8(TRAILERS_FLAG);
+    frame.put_u32(block.len() as u32);
+    frame.put_slice(&block);
+    frame.freeze()
+}
+
+#[allow(clippy::result_large_err)]
+fn decode_trailers(block: &[u8]) -> Result<HeaderMap, Status> {
+    let mut trailers = HeaderMap::new();
+
+    for line in block.split(|x| *x == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+
+        let invalid = || Status::internal("invalid gRPC-Web trailers");
+        let colon = line.iter().position(|x| *x == b':').ok_or_else(invalid)?;
+        let key =
+            HeaderName::from_bytes(line[..colon].trim_ascii().to_ascii_lowercase().as_slice())
+                .map_err(|_| invalid())?;
+        let value =
+            HeaderValue::from_bytes(line[colon + 1..].trim_ascii()).map_err(|_| invalid())?;
+        trailers.append(key, value);
+    }
+
+    Ok(trailers)
+}
+
+/// The CORS settings of [`GrpcWebService`].
+#[derive(Clone, Debug, Default)]
+pub struct CorsSettings {
+    /// the allowed origins, all origins are allowed if empty
+    pub allow_origins: Vec<String>,
+    /// the allowed request headers, the requested headers are allowed if empty
+    pub allow_headers: Vec<String>,
+    /// how long the preflight response can be cached, in seconds
+    pub max_age: Option<u64>,
+}
+
+impl CorsSettings {
+    fn allow_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        if self.allow_origins.is_empty() || self.allow_origins.iter().any(|x| x == "*") {
+            return Some(origin.clone());
+        }
+
+        self.allow_origins
+            .iter()
+            .any(|x| x.as_bytes() == origin.as_bytes())
+            .then(|| origin.clone())
+    }
+
+    /// Add the CORS headers of a response to a request from `origin`.
+    fn apply(&self, origin: Option<&HeaderValue>, headers: &mut HeaderMap) {
+        headers.append(VARY, HeaderValue::from_static("origin"));
+
+        let Some(origin) = origin.and_then(|x| self.allow_origin(x)) else {
+            return;
+        };
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(
+            ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static("grpc-status, grpc-message, grpc-status-details-bin"),
+        );
+    }
+
+    fn preflight(&self, req_headers: &HeaderMap) -> http::Response<TonicBody> {
+        let mut resp = http::Response::new(TonicBody::empty());
+        *resp.status_mut() = StatusCode::NO_CONTENT;
+
+        let headers = resp.headers_mut();
+        self.apply(req_headers.get(ORIGIN), headers);
+        if !headers.contains_key(ACCESS_CONTROL_ALLOW_ORIGIN) {
+            return resp;
+        }
+
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("POST, OPTIONS"),
+        );
+        let allow_headers = if self.allow_headers.is_empty() {
+            req_headers.get(ACCESS_CONTROL_REQUEST_HEADERS).cloned()
+        } else {
+            HeaderValue::from_str(&self.allow_headers.join(", ")).ok()
+        };
+        if let Some(allow_headers) = allow_headers {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.into());
+        }
+
+        resp
+    }
+}
+
+/// Serve gRPC-Web requests and CORS preflight requests with a gRPC service,
+/// native gRPC requests are passed through.
+#[derive(Clone, Debug)]
+pub struct GrpcWebService<S> {
+    inner: S,
+    cors: CorsSettings,
+}
+
+impl<S> GrpcWebService<S> {
+    pub fn new(inner: S, cors: CorsSettings) -> Self {
+        Self { inner, cors }
+    }
+}
+
+impl<S> Service<http::Request<TonicBody>> for GrpcWebService<S>
+where
+    S: Service<http::Request<TonicBody>, Response = http::Response<TonicBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = http::Response<TonicBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<TonicBody>) -> Self::Future {
+        let origin = req.headers().get(ORIGIN).cloned();
+
+        if req.method() == Method::OPTIONS {
+            let resp = self.cors.preflight(req.headers());
+            return Box::pin(async move { Ok(resp) });
+        }
+
+        let encoding = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .and_then(WebEncoding::from_content_type);
+        let Some(encoding) = encoding else {
+            // Connect requests from browsers need CORS too
+            let resp = self.inner.call(req);
+            let cors = self.cors.clone();
+            return Box::pin(async move {
+                let mut resp = resp.await?;
+                if origin.is_some() {
+                    cors.apply(origin.as_ref(), resp.headers_mut());
+                }
+                Ok(resp)
+            });
+        };
+
+        let (mut parts, body) = req.into_parts();
+        parts.headers.insert(CONTENT_TYPE, GRPC_CONTENT_TYPE);
+        let resp = self
+            .inner
+            .call(http::Request::from_parts(parts, encoding.decode_body(body)));
+
+        let cors = self.cors.clone();
+        Box::pin(async move {
+            let (mut parts, body) = resp.await?.into_parts();
+            parts.headers.insert(CONTENT_TYPE, encoding.content_type());
+            cors.apply(origin.as_ref(), &mut parts.headers);
+
+            Ok(http::Response::from_parts(
+                parts,
+                encoding.encode_body(body),
+            ))
+        })
+    }
+}
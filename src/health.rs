@@ -0,0 +1,244 @@
+//! The `grpc.health.v1.Health` service, see <https://github.com/grpc/grpc/blob/master/doc/health-checking.md>.
+
+use std::{
+    collections::BTreeMap,
+    future::{Ready, ready},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_util::{
+    StreamExt,
+    future::BoxFuture,
+    stream::{self, BoxStream},
+};
+use http::{Request, Response};
+use tokio::sync::watch;
+use tonic::{
+    Status,
+    body::Body as TonicBody,
+    codec::ProstCodec,
+    server::{Grpc, ServerStreamingService, UnaryService},
+};
+use tower_service::Service;
+
+use crate::shutdown::Shutdown;
+
+pub const CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+pub const WATCH_PATH: &str = "/grpc.health.v1.Health/Watch";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HealthCheckRequest {
+    #[prost(string, tag = "1")]
+    pub service: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct HealthCheckResponse {
+    #[prost(enumeration = "ServingStatus", tag = "1")]
+    pub status: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum ServingStatus {
+    Unknown = 0,
+    Serving = 1,
+    NotServing = 2,
+    /// only reported by `Watch`, `Check` fails with `NOT_FOUND` instead
+    ServiceUnknown = 3,
+}
+
+impl ServingStatus {
+    /// The name in the proto file, e.g. `NOT_SERVING`.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ServingStatus::Unknown => "UNKNOWN",
+            ServingStatus::Serving => "SERVING",
+            ServingStatus::NotServing => "NOT_SERVING",
+            ServingStatus::ServiceUnknown => "SERVICE_UNKNOWN",
+        }
+    }
+}
+
+/// Parse the health of a service in format of `service=status`, e.g. `helloworld.Greeter=not_serving`.
+/// The service is empty for the whole server.
+pub fn parse_health(s: &str) -> Result<(String, ServingStatus), String> {
+    let (service, status) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid health: {s}, expect `service=status`"))?;
+    let status = match status
+        .trim()
+        .to_ascii_uppercase()
+        .replace('-', "_")
+        .as_str()
+    {
+        "SERVING" => ServingStatus::Serving,
+        "NOT_SERVING" => ServingStatus::NotServing,
+        "UNKNOWN" => ServingStatus::Unknown,
+        _ => {
+            return Err(format!(
+                "unknown health status: {status}, expect `serving`, `not_serving` or `unknown`"
+            ));
+        }
+    };
+    Ok((service.trim().to_string(), status))
+}
+
+/// The statuses of the services, shared by the clones.
+#[derive(Clone, Debug)]
+pub struct Health(Arc<watch::Sender<BTreeMap<String, ServingStatus>>>);
+
+impl Health {
+    pub fn new(statuses: impl IntoIterator<Item = (String, ServingStatus)>) -> Self {
+        let (tx, _) = watch::channel(statuses.into_iter().collect());
+        Health(Arc::new(tx))
+    }
+
+    pub fn set(&self, service: &str, status: ServingStatus) {
+        self.0.send_modify(|x| {
+            x.insert(service.to_string(), status);
+        });
+    }
+
+    /// Set the status of all the services, e.g. `NOT_SERVING` on shutdown.
+    pub fn set_all(&self, status: ServingStatus) {
+        self.0
+            .send_modify(|x| x.values_mut().for_each(|x| *x = status));
+    }
+
+    pub fn get(&self, service: &str) -> Option<ServingStatus> {
+        self.0.borrow().get(service).copied()
+    }
+
+    /// The status of the service, then every change of it.
+    pub fn watch(&self, service: String) -> BoxStream<'static, ServingStatus> {
+        stream::unfold((self.0.subscribe(), None), move |(mut rx, last)| {
+            let service = service.clone();
+            async move {
+                loop {
+                    let status = rx
+                        .borrow_and_update()
+                        .get(&service)
+                        .copied()
+                        .unwrap_or(ServingStatus::ServiceUnknown);
+                    if last != Some(status) {
+                        return Some((status, (rx, Some(status))));
+                    }
+                    rx.changed().await.ok()?;
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+struct CheckService {
+    health: Health,
+}
+
+impl UnaryService<HealthCheckRequest> for CheckService {
+    type Response = HealthCheckResponse;
+    type Future = Ready<Result<tonic::Response<Self::Response>, Status>>;
+
+    fn call(&mut self, req: tonic::Request<HealthCheckRequest>) -> Self::Future {
+        let service = &req.get_ref().service;
+        let resp = match self.health.get(service) {
+            Some(status) => Ok(tonic::Response::new(HealthCheckResponse {
+                status: status as i32,
+            })),
+            None => Err(Status::not_found(format!("unknown service: {service}"))),
+        };
+        ready(resp)
+    }
+}
+
+struct WatchService {
+    health: Health,
+    shutdown: Option<Shutdown>,
+}
+
+impl ServerStreamingService<HealthCheckRequest> for WatchService {
+    type Response = HealthCheckResponse;
+    type ResponseStream = BoxStream<'static, tonic::Result<HealthCheckResponse>>;
+    type Future = BoxFuture<'static, tonic::Result<tonic::Response<Self::ResponseStream>>>;
+
+    fn call(&mut self, req: tonic::Request<HealthCheckRequest>) -> Self::Future {
+        let statuses = self.health.watch(req.into_inner().service);
+
+        // the watches never end, they are told the server is going away on shutdown
+        let statuses = match self.shutdown.clone() {
+            Some(shutdown) => statuses
+                .take_until(shutdown.wait())
+                .chain(stream::once(ready(ServingStatus::NotServing)))
+                .boxed(),
+            None => statuses,
+        };
+        let stream = statuses
+            .map(|status| HealthCheckResponse {
+                status: status as i32,
+            })
+            .map(Ok)
+            .boxed();
+
+        Box::pin(ready(Ok(tonic::Response::new(stream))))
+    }
+}
+
+/// Serve `grpc.health.v1.Health`, the other requests are passed to the inner service.
+#[derive(Clone, Debug)]
+pub struct HealthService<S> {
+    inner: S,
+    health: Health,
+    shutdown: Option<Shutdown>,
+}
+
+impl<S> HealthService<S> {
+    pub fn new(inner: S, health: Health) -> Self {
+        Self {
+            inner,
+            health,
+            shutdown: None,
+        }
+    }
+
+    /// End the watches with `NOT_SERVING` on shutdown.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+}
+
+impl<S> Service<Request<TonicBody>> for HealthService<S>
+where
+    S: Service<Request<TonicBody>, Response = Response<TonicBody>> + Send,
+    S::Future: Send + 'static,
+{
+    type Response = Response<TonicBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<TonicBody>) -> Self::Future {
+        let mut grpc = Grpc::new(ProstCodec::<HealthCheckResponse, HealthCheckRequest>::default());
+        match req.uri().path() {
+            CHECK_PATH => {
+                let s = CheckService {
+                    health: self.health.clone(),
+                };
+                Box::pin(async move { Ok(grpc.unary(s, req).await) })
+            }
+            WATCH_PATH => {
+                let s = WatchService {
+                    health: self.health.clone(),
+                    shutdown: self.shutdown.clone(),
+                };
+                Box::pin(async move { Ok(grpc.server_streaming(s, req).await) })
+            }
+            _ => Box::pin(self.inner.call(req)),
+        }
+    }
+}
@@ -1,10 +1,27 @@
+mod address;
+mod any;
 mod cmd;
 mod codec;
+mod connect;
+mod connectrpc;
 mod descriptor_set;
+mod encoding;
+mod format;
+mod framing;
+mod grpc_web;
+mod health;
 mod json;
+mod proxy;
+mod raw;
+mod retry;
+mod service_config;
+mod shutdown;
 mod static_server;
 mod tls;
+mod transcode;
+mod unknown;
 mod util;
+mod validate;
 
 use argh::FromArgs;
 use cmd::Executable;
@@ -13,11 +30,14 @@ use self::cmd::Command;
 
 /**
 Useful functions for interacting with gRPC, including:
-  * dummy server (only one method each running)
+  * dummy server of one method, serving `grpc.health.v1.Health` next to it
   * client
+  * load testing
+  * REST/JSON gateway of the `google.api.http` annotated methods
+  * health probe of `grpc.health.v1.Health`
   * protobuf compiler
   * protobuf descriptor inspector
-  * protobuf binary-json converter
+  * protobuf binary-json/text format converter
 
 Before running the program, you need to precompile the protobuf files into protobuf file descriptors.
 you can either use the `protoc` command or the grpc-cli builtin command `compile` to do this.
@@ -44,7 +64,10 @@ impl App {
             Command::Inspect(x) => x,
             Command::Json(x) => x,
             Command::Client(x) => x,
+            Command::Bench(x) => x,
             Command::Server(x) => x,
+            Command::Gateway(x) => x,
+            Command::Health(x) => x,
             Command::Version(x) => x,
         };
 
@@ -0,0 +1,119 @@
+//! Schema-less protobuf wire format decoding, similar to `protoc --decode_raw`.
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use prost::encoding::decode_varint;
+use serde_json::{Value, json};
+
+/// The recursion limit for guessing nested messages and groups, same as prost.
+const RECURSION_LIMIT: u32 = 100;
+
+/// Decode the protobuf wire format without a schema into a JSON array of fields.
+pub fn decode_raw(mut buf: &[u8]) -> anyhow::Result<Value> {
+    let fields = decode_fields(&mut buf, None, RECURSION_LIMIT)?;
+    Ok(Value::Array(fields))
+}
+
+fn decode_fields(buf: &mut &[u8], group: Option<u32>, depth: u32) -> anyhow::Result<Vec<Value>> {
+    if depth == 0 {
+        anyhow::bail!("recursion limit reached");
+    }
+
+    let mut fields = Vec::new();
+
+    while !buf.is_empty() {
+        let key = decode_varint(buf)?;
+        let wire_type = key & 0x07;
+        let number = u32::try_from(key >> 3)
+            .ok()
+            .filter(|n| (1..=0x1fff_ffff).contains(n))
+            .ok_or_else(|| anyhow::anyhow!("invalid field number: {}", key >> 3))?;
+
+        let field = match wire_type {
+            0 => {
+                let v = decode_varint(buf)?;
+                json!({
+                    "field": number,
+                    "wire_type": "varint",
+                    "uint": v,
+                    "int": v as i64,
+                    "sint": ((v >> 1) as i64) ^ -((v & 1) as i64),
+                })
+            }
+            1 => {
+                let v = u64::from_le_bytes(take(buf, 8)?.try_into().unwrap());
+                json!({
+                    "field": number,
+                    "wire_type": "fixed64",
+                    "uint": v,
+                    "int": v as i64,
+                    "double": f64::from_bits(v),
+                })
+            }
+            2 => {
+                let len = usize::try_from(decode_varint(buf)?)?;
+                let data = take(buf, len)?;
+                let mut field = json!({
+                    "field": number,
+                    "wire_type": "len",
+                });
+                let (kind, value) = decode_len(data, depth);
+                field[kind] = value;
+                field
+            }
+            3 => json!({
+                "field": number,
+                "wire_type": "group",
+                "group": decode_fields(buf, Some(number), depth - 1)?,
+            }),
+            4 => {
+                if group != Some(number) {
+                    anyhow::bail!("unexpected end group tag for field {number}");
+                }
+                return Ok(fields);
+            }
+            5 => {
+                let v = u32::from_le_bytes(take(buf, 4)?.try_into().unwrap());
+                json!({
+                    "field": number,
+                    "wire_type": "fixed32",
+                    "uint": v,
+                    "int": v as i32,
+                    "float": f32::from_bits(v),
+                })
+            }
+            _ => anyhow::bail!("invalid wire type {wire_type} for field {number}"),
+        };
+
+        fields.push(field);
+    }
+
+    if let Some(number) = group {
+        anyhow::bail!("missing end group tag for field {number}");
+    }
+
+    Ok(fields)
+}
+
+pub(crate) fn take<'a>(buf: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if buf.len() < len {
+        anyhow::bail!("unexpected end of input");
+    }
+    let (data, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(data)
+}
+
+/// Printable UTF-8 is most likely a string, otherwise try it as a nested message,
+/// and fall back to the raw bytes in base64.
+fn decode_len(data: &[u8], depth: u32) -> (&'static str, Value) {
+    if let Ok(s) = std::str::from_utf8(data)
+        && s.chars().all(|c| !c.is_control() || c.is_whitespace())
+    {
+        return ("string", Value::String(s.to_owned()));
+    }
+
+    match decode_fields(&mut &data[..], None, depth - 1) {
+        Ok(fields) if !fields.is_empty() => ("message", Value::Array(fields)),
+        _ => ("bytes", Value::String(BASE64_STANDARD.encode(data))),
+    }
+}
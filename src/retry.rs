@@ -0,0 +1,196 @@
+//! Retrying and hedging the calls, following the gRPC retry design.
+//!
+//! A call is only retried until the first response message is received,
+//! the attempts after that would duplicate the messages already handled.
+
+use std::{
+    future::Future,
+    hash::{BuildHasher, RandomState},
+    time::Duration,
+};
+
+use futures_util::{StreamExt, stream::FuturesUnordered};
+use tonic::{Code, Status};
+
+/// The most attempts of a call, larger values are clamped like other gRPC clients.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// The metadata key the server can set to delay or stop the retries.
+const RETRY_PUSHBACK_KEY: &str = "grpc-retry-pushback-ms";
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub backoff_multiplier: f64,
+    pub retryable_status_codes: Vec<Code>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HedgingPolicy {
+    pub max_attempts: u32,
+    pub hedging_delay: Duration,
+    pub non_fatal_status_codes: Vec<Code>,
+}
+
+/// How the attempts of a call are made, the retries and the hedging are exclusive.
+#[derive(Clone, Debug, Default)]
+pub enum CallPolicy {
+    #[default]
+    Once,
+    Retry(RetryPolicy),
+    Hedging(HedgingPolicy),
+}
+
+impl CallPolicy {
+    /// Make the attempts until one succeeds, given the attempt number starting from 1.
+    /// The attempts are logged to stderr if `verbose`.
+    pub async fn call<T, F, Fut>(&self, verbose: bool, attempt: F) -> anyhow::Result<T>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        match self {
+            CallPolicy::Once => {
+                let mut attempt = attempt;
+                attempt(1).await
+            }
+            CallPolicy::Retry(policy) => retry(policy, verbose, attempt).await,
+            CallPolicy::Hedging(policy) => hedge(policy, verbose, attempt).await,
+        }
+    }
+}
+
+async fn retry<T, F, Fut>(policy: &RetryPolicy, verbose: bool, mut attempt: F) -> anyhow::Result<T>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let max_attempts = policy.max_attempts.clamp(1, MAX_ATTEMPTS);
+    let mut backoff = policy.initial_backoff;
+    let mut n = 1;
+    loop {
+        if verbose {
+            eprintln!("attempt {n}/{max_attempts}");
+        }
+        let err = match attempt(n).await {
+            Ok(x) => return Ok(x),
+            Err(e) => e,
+        };
+
+        let status = err.downcast_ref::<Status>();
+        let code = status.map_or(Code::Unknown, Status::code);
+        if n >= max_attempts || !policy.retryable_status_codes.contains(&code) {
+            return Err(err);
+        }
+
+        // the server may ask to wait longer, or not to retry at all with a negative value
+        let delay = match status.and_then(|x| x.metadata().get(RETRY_PUSHBACK_KEY)) {
+            Some(value) => match value.to_str().ok().and_then(|x| x.parse::<u64>().ok()) {
+                Some(ms) => Duration::from_millis(ms),
+                None => return Err(err),
+            },
+            None => jitter(backoff),
+        };
+        if verbose {
+            eprintln!("attempt {n} failed with {code:?}, retrying in {delay:?}");
+        }
+        tokio::time::sleep(delay).await;
+
+        backoff = next_backoff(policy, backoff);
+        n += 1;
+    }
+}
+
+async fn hedge<T, F, Fut>(
+    policy: &HedgingPolicy,
+    verbose: bool,
+    mut attempt: F,
+) -> anyhow::Result<T>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let max_attempts = policy.max_attempts.clamp(1, MAX_ATTEMPTS);
+    let mut attempts = FuturesUnordered::new();
+    let mut started = 0;
+    loop {
+        if started < max_attempts {
+            started += 1;
+            if verbose {
+                eprintln!("attempt {started}/{max_attempts}");
+            }
+            let n = started;
+            let fut = attempt(n);
+            attempts.push(async move { (n, fut.await) });
+        }
+
+        // wait for the next attempt to finish, or start another one after the delay
+        let next = if started < max_attempts {
+            match tokio::time::timeout(policy.hedging_delay, attempts.next()).await {
+                Ok(next) => next,
+                Err(_) => continue,
+            }
+        } else {
+            attempts.next().await
+        };
+
+        let Some((n, result)) = next else {
+            unreachable!("an attempt is always in flight");
+        };
+        let err = match result {
+            Ok(x) => return Ok(x),
+            Err(e) => e,
+        };
+
+        // the other attempts are cancelled when dropped
+        let code = err
+            .downcast_ref::<Status>()
+            .map_or(Code::Unknown, Status::code);
+        if !policy.non_fatal_status_codes.contains(&code)
+            || (attempts.is_empty() && started >= max_attempts)
+        {
+            return Err(err);
+        }
+        if verbose {
+            eprintln!("attempt {n} failed with {code:?}");
+        }
+    }
+}
+
+/// Multiply the backoff in seconds, a huge product would overflow `Duration`.
+fn next_backoff(policy: &RetryPolicy, backoff: Duration) -> Duration {
+    let secs = backoff.as_secs_f64() * policy.backoff_multiplier;
+    Duration::try_from_secs_f64(secs.min(policy.max_backoff.as_secs_f64()))
+        .unwrap_or(policy.max_backoff)
+}
+
+/// A random duration up to the backoff, so that the clients don't retry all at once.
+fn jitter(backoff: Duration) -> Duration {
+    let random = RandomState::new().hash_one(std::time::Instant::now());
+    backoff.mul_f64((random >> 11) as f64 / (1u64 << 53) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let policy = |backoff_multiplier| RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            backoff_multiplier,
+            retryable_status_codes: vec![Code::Unavailable],
+        };
+        let next = |multiplier, ms| next_backoff(&policy(multiplier), Duration::from_millis(ms));
+
+        assert_eq!(next(2.0, 100), Duration::from_millis(200));
+        assert_eq!(next(2.0, 800), Duration::from_secs(1));
+        assert_eq!(next(0.5, 100), Duration::from_millis(50));
+        assert_eq!(next(1e30, 100), Duration::from_secs(1));
+        assert_eq!(next(f64::INFINITY, 100), Duration::from_secs(1));
+    }
+}
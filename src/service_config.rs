@@ -0,0 +1,397 @@
+//! The gRPC service config, see <https://github.com/grpc/grpc/blob/master/doc/service_config.md>.
+//!
+//! Only the settings which make sense for a single call are honored:
+//! the load balancing policy and the `methodConfig` of the called method.
+
+use std::{path::Path, time::Duration};
+
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+use crate::{
+    retry::{CallPolicy, HedgingPolicy, RetryPolicy},
+    util::{parse_code, parse_duration},
+};
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceConfig {
+    /// deprecated in favor of `loadBalancingConfig`, but still common
+    load_balancing_policy: Option<String>,
+    #[serde(default)]
+    load_balancing_config: Vec<serde_json::Map<String, JsonValue>>,
+    #[serde(default)]
+    method_config: Vec<RawMethodConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RawMethodConfig {
+    #[serde(default)]
+    name: Vec<Name>,
+    wait_for_ready: Option<bool>,
+    timeout: Option<String>,
+    max_request_message_bytes: Option<JsonValue>,
+    max_response_message_bytes: Option<JsonValue>,
+    retry_policy: Option<RawRetryPolicy>,
+    hedging_policy: Option<RawHedgingPolicy>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Name {
+    #[serde(default)]
+    service: String,
+    #[serde(default)]
+    method: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RawRetryPolicy {
+    max_attempts: u32,
+    initial_backoff: String,
+    max_backoff: String,
+    backoff_multiplier: f64,
+    retryable_status_codes: Vec<JsonValue>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RawHedgingPolicy {
+    max_attempts: u32,
+    hedging_delay: Option<String>,
+    #[serde(default)]
+    non_fatal_status_codes: Vec<JsonValue>,
+}
+
+/// The settings of the `methodConfig` applied to a method.
+#[derive(Debug, Default)]
+pub struct MethodConfig {
+    pub wait_for_ready: bool,
+    pub timeout: Option<Duration>,
+    pub max_request_message_bytes: Option<usize>,
+    pub max_response_message_bytes: Option<usize>,
+    pub policy: CallPolicy,
+}
+
+/// The load balancing policies known by the client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadBalancing {
+    PickFirst,
+    RoundRobin,
+}
+
+impl ServiceConfig {
+    /// Load the config from the inline JSON, or the file at the path otherwise.
+    pub fn load(s: &str) -> anyhow::Result<Self> {
+        let json = if s.trim_start().starts_with('{') {
+            s.to_string()
+        } else {
+            std::fs::read_to_string(Path::new(s))
+                .map_err(|e| anyhow::anyhow!("failed to read the service config {s}: {e}"))?
+        };
+        let config: ServiceConfig = serde_json::from_str(&json)
+            .map_err(|e| anyhow::anyhow!("invalid service config: {e}"))?;
+
+        // report the mistakes early, not only when the faulty method is called
+        for method_config in &config.method_config {
+            method_config.parse()?;
+        }
+        config.load_balancing()?;
+
+        Ok(config)
+    }
+
+    /// The first known policy of `loadBalancingConfig`, or `loadBalancingPolicy`.
+    pub fn load_balancing(&self) -> anyhow::Result<Option<LoadBalancing>> {
+        let known = |name: &str| match name.to_ascii_lowercase().as_str() {
+            "pick_first" => Some(LoadBalancing::PickFirst),
+            "round_robin" => Some(LoadBalancing::RoundRobin),
+            _ => None,
+        };
+
+        if !self.load_balancing_config.is_empty() {
+            return self
+                .load_balancing_config
+                .iter()
+                .flat_map(|x| x.keys())
+                .find_map(|x| known(x))
+                .map(Some)
+                .ok_or_else(|| anyhow::anyhow!("no supported policy in `loadBalancingConfig`"));
+        }
+
+        match &self.load_balancing_policy {
+            Some(name) => known(name)
+                .map(Some)
+                .ok_or_else(|| anyhow::anyhow!("unsupported `loadBalancingPolicy`: {name}")),
+            None => Ok(None),
+        }
+    }
+
+    /// The config of the method, e.g. `helloworld.Greeter` and `SayHello`.
+    /// The exact name takes precedence over the service name, then the default with an empty name.
+    pub fn method_config(&self, service: &str, method: &str) -> anyhow::Result<MethodConfig> {
+        let find = |service: &str, method: &str| {
+            self.method_config.iter().find(|x| {
+                x.name
+                    .iter()
+                    .any(|name| name.service == service && name.method == method)
+            })
+        };
+
+        let config = find(service, method)
+            .or_else(|| find(service, ""))
+            .or_else(|| find("", ""));
+        match config {
+            Some(config) => config.parse(),
+            None => Ok(MethodConfig::default()),
+        }
+    }
+}
+
+impl RawMethodConfig {
+    fn parse(&self) -> anyhow::Result<MethodConfig> {
+        if self.retry_policy.is_some() && self.hedging_policy.is_some() {
+            anyhow::bail!("`retryPolicy` and `hedgingPolicy` are exclusive");
+        }
+        for name in &self.name {
+            if name.service.is_empty() && !name.method.is_empty() {
+                anyhow::bail!("the method {} of `name` has no service", name.method);
+            }
+        }
+
+        let policy = match (&self.retry_policy, &self.hedging_policy) {
+            (Some(retry), _) => {
+                let policy = RetryPolicy {
+                    max_attempts: retry.max_attempts,
+                    initial_backoff: duration(&retry.initial_backoff)?,
+                    max_backoff: duration(&retry.max_backoff)?,
+                    backoff_multiplier: retry.backoff_multiplier,
+                    retryable_status_codes: codes(&retry.retryable_status_codes)?,
+                };
+                if policy.max_attempts < 2
+                    || policy.initial_backoff.is_zero()
+                    || policy.max_backoff.is_zero()
+                    || policy.backoff_multiplier <= 0.0
+                    || policy.retryable_status_codes.is_empty()
+                {
+                    anyhow::bail!("invalid `retryPolicy`: {retry:?}");
+                }
+                CallPolicy::Retry(policy)
+            }
+            (None, Some(hedging)) => {
+                let policy = HedgingPolicy {
+                    max_attempts: hedging.max_attempts,
+                    hedging_delay: match &hedging.hedging_delay {
+                        Some(x) => duration(x)?,
+                        None => Duration::ZERO,
+                    },
+                    non_fatal_status_codes: codes(&hedging.non_fatal_status_codes)?,
+                };
+                if policy.max_attempts < 2 {
+                    anyhow::bail!("invalid `hedgingPolicy`: {hedging:?}");
+                }
+                CallPolicy::Hedging(policy)
+            }
+            (None, None) => CallPolicy::Once,
+        };
+
+        Ok(MethodConfig {
+            wait_for_ready: self.wait_for_ready.unwrap_or_default(),
+            timeout: self.timeout.as_deref().map(duration).transpose()?,
+            max_request_message_bytes: self
+                .max_request_message_bytes
+                .as_ref()
+                .map(size)
+                .transpose()?,
+            max_response_message_bytes: self
+                .max_response_message_bytes
+                .as_ref()
+                .map(size)
+                .transpose()?,
+            policy,
+        })
+    }
+}
+
+/// The durations are in the protobuf JSON format, e.g. `1.5s`.
+fn duration(s: &str) -> anyhow::Result<Duration> {
+    let is_seconds = s
+        .strip_suffix('s')
+        .is_some_and(|x| !x.is_empty() && x.chars().all(|c| c.is_ascii_digit() || c == '.'));
+    if !is_seconds {
+        anyhow::bail!("invalid duration: {s}, expect seconds like `1.5s`");
+    }
+    parse_duration(s).map_err(|e| anyhow::anyhow!(e))
+}
+
+/// The sizes are numbers, or strings of the 64-bit integers in the protobuf JSON format.
+fn size(value: &JsonValue) -> anyhow::Result<usize> {
+    let size = match value {
+        JsonValue::Number(x) => x.as_u64(),
+        JsonValue::String(x) => x.parse().ok(),
+        _ => None,
+    };
+    size.and_then(|x| usize::try_from(x).ok())
+        .ok_or_else(|| anyhow::anyhow!("invalid message size: {value}"))
+}
+
+/// The status codes are names like `UNAVAILABLE`, or numbers.
+fn codes(values: &[JsonValue]) -> anyhow::Result<Vec<tonic::Code>> {
+    values
+        .iter()
+        .map(|value| {
+            let code = match value {
+                JsonValue::String(x) => parse_code(x),
+                JsonValue::Number(x) => parse_code(&x.to_string()),
+                _ => Err(format!("invalid status code: {value}")),
+            };
+            code.map_err(|e| anyhow::anyhow!(e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeout(config: &ServiceConfig, service: &str, method: &str) -> Option<Duration> {
+        config.method_config(service, method).unwrap().timeout
+    }
+
+    #[test]
+    fn method_name_precedence() {
+        let config = ServiceConfig::load(
+            r#"{"methodConfig": [
+                {"name": [{}], "timeout": "1s"},
+                {"name": [{"service": "a.S"}], "timeout": "2s"},
+                {"name": [{"service": "a.S", "method": "M"}, {"service": "b.S", "method": "M"}],
+                 "timeout": "3s"}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(timeout(&config, "a.S", "M"), Some(Duration::from_secs(3)));
+        assert_eq!(timeout(&config, "b.S", "M"), Some(Duration::from_secs(3)));
+        assert_eq!(timeout(&config, "a.S", "N"), Some(Duration::from_secs(2)));
+        assert_eq!(timeout(&config, "b.S", "N"), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn no_matching_method() {
+        let config = ServiceConfig::load(
+            r#"{"methodConfig": [{"name": [{"service": "a.S"}], "waitForReady": true}]}"#,
+        )
+        .unwrap();
+
+        let method_config = config.method_config("b.S", "M").unwrap();
+        assert!(!method_config.wait_for_ready);
+        assert!(matches!(method_config.policy, CallPolicy::Once));
+        assert!(config.method_config("a.S", "M").unwrap().wait_for_ready);
+    }
+
+    #[test]
+    fn load_balancing() {
+        let lb = |json: &str| ServiceConfig::load(json).map(|x| x.load_balancing().unwrap());
+
+        assert_eq!(lb("{}").unwrap(), None);
+        assert_eq!(
+            lb(r#"{"loadBalancingPolicy": "ROUND_ROBIN"}"#).unwrap(),
+            Some(LoadBalancing::RoundRobin)
+        );
+        // the first known policy wins, and takes precedence over `loadBalancingPolicy`
+        assert_eq!(
+            lb(r#"{"loadBalancingPolicy": "round_robin",
+                   "loadBalancingConfig": [{"weighted_round_robin": {}}, {"pick_first": {}}]}"#)
+            .unwrap(),
+            Some(LoadBalancing::PickFirst)
+        );
+        assert!(lb(r#"{"loadBalancingConfig": [{"grpclb": {}}]}"#).is_err());
+        assert!(lb(r#"{"loadBalancingPolicy": "grpclb"}"#).is_err());
+    }
+
+    #[test]
+    fn retry_policy() {
+        let config = ServiceConfig::load(
+            r#"{"methodConfig": [{"name": [{}], "retryPolicy": {
+                "maxAttempts": 3, "initialBackoff": "0.1s", "maxBackoff": "1s",
+                "backoffMultiplier": 2, "retryableStatusCodes": ["UNAVAILABLE", 4]
+            }}]}"#,
+        )
+        .unwrap();
+
+        let CallPolicy::Retry(policy) = config.method_config("a.S", "M").unwrap().policy else {
+            panic!("expect a retry policy");
+        };
+        assert_eq!(policy.max_attempts, 3);
+        assert_eq!(policy.initial_backoff, Duration::from_millis(100));
+        assert_eq!(policy.max_backoff, Duration::from_secs(1));
+        assert_eq!(
+            policy.retryable_status_codes,
+            [tonic::Code::Unavailable, tonic::Code::DeadlineExceeded]
+        );
+    }
+
+    #[test]
+    fn hedging_policy() {
+        let config = ServiceConfig::load(
+            r#"{"methodConfig": [{"name": [{}], "hedgingPolicy": {"maxAttempts": 2}}]}"#,
+        )
+        .unwrap();
+
+        let CallPolicy::Hedging(policy) = config.method_config("a.S", "M").unwrap().policy else {
+            panic!("expect a hedging policy");
+        };
+        assert_eq!(policy.max_attempts, 2);
+        assert_eq!(policy.hedging_delay, Duration::ZERO);
+        assert!(policy.non_fatal_status_codes.is_empty());
+    }
+
+    #[test]
+    fn invalid_method_configs() {
+        let retry = r#""retryPolicy": {"maxAttempts": 3, "initialBackoff": "0.1s",
+            "maxBackoff": "1s", "backoffMultiplier": 2, "retryableStatusCodes": ["UNAVAILABLE"]}"#;
+        let invalid = [
+            // exclusive policies
+            format!(r#"{{"name": [{{}}], {retry}, "hedgingPolicy": {{"maxAttempts": 2}}}}"#),
+            // too few attempts
+            r#"{"name": [{}], "hedgingPolicy": {"maxAttempts": 1}}"#.to_string(),
+            retry.replace(r#""maxAttempts": 3"#, r#""maxAttempts": 1"#),
+            // nothing to retry on
+            retry.replace(r#"["UNAVAILABLE"]"#, "[]"),
+            // unknown status code
+            retry.replace("UNAVAILABLE", "NOT_A_CODE"),
+            // zero backoff
+            retry.replace(r#""initialBackoff": "0.1s""#, r#""initialBackoff": "0s""#),
+            // durations must be in seconds
+            r#"{"name": [{}], "timeout": "100ms"}"#.to_string(),
+            // a method needs its service
+            r#"{"name": [{"method": "M"}]}"#.to_string(),
+            // sizes are unsigned integers
+            r#"{"name": [{}], "maxRequestMessageBytes": -1}"#.to_string(),
+        ];
+
+        for method_config in invalid {
+            let method_config = if method_config.starts_with('{') {
+                method_config
+            } else {
+                format!(r#"{{"name": [{{}}], {method_config}}}"#)
+            };
+            let json = format!(r#"{{"methodConfig": [{method_config}]}}"#);
+            assert!(ServiceConfig::load(&json).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn message_sizes() {
+        let config = ServiceConfig::load(
+            r#"{"methodConfig": [{"name": [{}],
+                "maxRequestMessageBytes": 1024, "maxResponseMessageBytes": "2048"}]}"#,
+        )
+        .unwrap();
+
+        let method_config = config.method_config("a.S", "M").unwrap();
+        assert_eq!(method_config.max_request_message_bytes, Some(1024));
+        assert_eq!(method_config.max_response_message_bytes, Some(2048));
+    }
+}
@@ -0,0 +1,73 @@
+//! Shutting down the servers gracefully on SIGINT and SIGTERM, or Ctrl-C on other platforms.
+
+#[cfg(unix)]
+use std::pin::pin;
+
+#[cfg(unix)]
+use futures_util::future::select;
+#[cfg(unix)]
+use tokio::signal::unix::{Signal, SignalKind, signal};
+use tokio::sync::watch;
+
+/// Notified once the server is asked to shut down, the clones share the notification.
+#[derive(Clone, Debug)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Listen to SIGINT and SIGTERM, a second signal exits at once.
+    /// It must be called within the runtime.
+    pub fn on_signal() -> anyhow::Result<Self> {
+        let mut signals = Signals::new()?;
+        let (tx, rx) = watch::channel(false);
+
+        tokio::spawn(async move {
+            signals.recv().await;
+            tx.send_replace(true);
+
+            // the sender is kept alive, or the receivers would see a shutdown
+            signals.recv().await;
+            eprintln!("exiting without waiting for the calls in flight");
+            std::process::exit(1);
+        });
+
+        Ok(Shutdown(rx))
+    }
+
+    /// Wait for the shutdown to be asked.
+    pub async fn wait(mut self) {
+        let _ = self.0.wait_for(|x| *x).await;
+    }
+}
+
+/// SIGINT and SIGTERM on Unix, only Ctrl-C elsewhere.
+struct Signals {
+    #[cfg(unix)]
+    interrupt: Signal,
+    #[cfg(unix)]
+    terminate: Signal,
+}
+
+impl Signals {
+    #[cfg(unix)]
+    fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            interrupt: signal(SignalKind::interrupt())?,
+            terminate: signal(SignalKind::terminate())?,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn new() -> anyhow::Result<Self> {
+        Ok(Self {})
+    }
+
+    #[cfg(unix)]
+    async fn recv(&mut self) {
+        select(pin!(self.interrupt.recv()), pin!(self.terminate.recv())).await;
+    }
+
+    #[cfg(not(unix))]
+    async fn recv(&mut self) {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
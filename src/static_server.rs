@@ -15,14 +15,15 @@ use prost_reflect::{DynamicMessage, MethodDescriptor};
 use tokio::time;
 use tokio_stream::wrappers::IntervalStream;
 use tonic::{
-    Status,
+    Code, Status,
     body::Body as TonicBody,
+    codec::CompressionEncoding,
     metadata::GRPC_CONTENT_TYPE,
     server::{Grpc, ServerStreamingService, UnaryService},
 };
 use tower_service::Service;
 
-use crate::codec::DynamicProstCodec;
+use crate::{codec::DynamicProstCodec, shutdown::Shutdown};
 
 type StdError = Box<dyn std::error::Error + Send + Sync + 'static>;
 type BoxResultFuture<T, E> = BoxFuture<'static, Result<T, E>>;
@@ -44,6 +45,7 @@ struct InnerServerStreamingService {
     resp_body: DynamicMessage,
 
     stream_cycle: Option<Duration>,
+    shutdown: Option<(Shutdown, Code)>,
 }
 
 type StreamItem = tonic::Result<DynamicMessage>;
@@ -56,18 +58,30 @@ impl ServerStreamingService<DynamicMessage> for InnerServerStreamingService {
     fn call(&mut self, _: tonic::Request<DynamicMessage>) -> Self::Future {
         let resp_body = self.resp_body.clone();
         let stream_cycle = self.stream_cycle;
+        let shutdown = self.shutdown.clone();
         Box::pin(async move {
             let stream = match stream_cycle {
-                Some(cycle) => {
-                    let s = IntervalStream::new(time::interval(cycle))
-                        .map(move |_| Ok(resp_body.clone()))
-                        .boxed();
-                    return Ok(tonic::Response::new(s));
-                }
+                Some(cycle) => IntervalStream::new(time::interval(cycle))
+                    .map(move |_| resp_body.clone())
+                    .map(Ok)
+                    .boxed(),
                 None => stream::once(ready(Ok(resp_body.clone())))
                     .chain(pending())
                     .boxed(),
             };
+
+            // the streams never end, or the server couldn't be drained
+            let stream = match shutdown {
+                Some((shutdown, Code::Ok)) => stream.take_until(shutdown.wait()).boxed(),
+                Some((shutdown, code)) => stream
+                    .take_until(shutdown.wait())
+                    .chain(stream::once(ready(Err(Status::new(
+                        code,
+                        "the server is shutting down",
+                    )))))
+                    .boxed(),
+                None => stream,
+            };
             Ok(tonic::Response::new(stream))
         })
     }
@@ -81,6 +95,16 @@ pub struct StaticService {
     response: DynamicMessage,
 
     stream_cycle: Option<Duration>,
+    settings: GrpcSettings,
+    shutdown: Option<(Shutdown, Code)>,
+}
+
+/// The gRPC protocol level settings of [`StaticService`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrpcSettings {
+    pub send_compression: Option<CompressionEncoding>,
+    pub max_decoding_message_size: Option<usize>,
+    pub max_encoding_message_size: Option<usize>,
 }
 
 impl StaticService {
@@ -91,6 +115,7 @@ impl StaticService {
         method_type: MethodDescriptor,
         response: DynamicMessage,
         stream_cycle: Option<Duration>,
+        settings: GrpcSettings,
     ) -> anyhow::Result<Self> {
         let served_uri = Uri::from_maybe_shared(format!("/{service}/{method}"))?;
 
@@ -101,8 +126,40 @@ impl StaticService {
             response,
 
             stream_cycle,
+            settings,
+            shutdown: None,
         })
     }
+
+    /// End the server streams with the status code on shutdown, or successfully with `OK`.
+    pub fn with_shutdown(mut self, shutdown: Shutdown, code: Code) -> Self {
+        self.shutdown = Some((shutdown, code));
+        self
+    }
+
+    /// The path of the served method, e.g. `/helloworld.Greeter/SayHello`.
+    pub fn served_uri(&self) -> &Uri {
+        &self.served_uri
+    }
+
+    fn grpc(&self) -> Grpc<DynamicProstCodec> {
+        let mut grpc = Grpc::new(self.codec.clone())
+            .accept_compressed(CompressionEncoding::Gzip)
+            .accept_compressed(CompressionEncoding::Zstd)
+            .accept_compressed(CompressionEncoding::Deflate);
+
+        if let Some(encoding) = self.settings.send_compression {
+            grpc = grpc.send_compressed(encoding);
+        }
+        if let Some(limit) = self.settings.max_decoding_message_size {
+            grpc = grpc.max_decoding_message_size(limit);
+        }
+        if let Some(limit) = self.settings.max_encoding_message_size {
+            grpc = grpc.max_encoding_message_size(limit);
+        }
+
+        grpc
+    }
 }
 
 impl<B> Service<Request<B>> for StaticService
@@ -132,22 +189,23 @@ where
             return Box::pin(ready(Ok(response)));
         }
 
-        let codec = self.codec.clone();
+        let mut grpc = self.grpc();
 
         if self.method_type.is_server_streaming() {
             let s = InnerServerStreamingService {
                 resp_body: self.response.clone(),
 
                 stream_cycle: self.stream_cycle,
+                shutdown: self.shutdown.clone(),
             };
 
-            Box::pin(async move { Ok(Grpc::new(codec).server_streaming(s, req).await) })
+            Box::pin(async move { Ok(grpc.server_streaming(s, req).await) })
         } else {
             let s = InnerUnaryService {
                 resp_body: self.response.clone(),
             };
 
-            Box::pin(async move { Ok(Grpc::new(codec).unary(s, req).await) })
+            Box::pin(async move { Ok(grpc.unary(s, req).await) })
         }
     }
 }
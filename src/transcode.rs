@@ -0,0 +1,727 @@
+//! [HTTP/JSON transcoding](https://cloud.google.com/endpoints/docs/grpc/transcoding) of the methods
+//! annotated with `google.api.http`. The path, the query and the body of a REST request are mapped
+//! to the request message, and the response message is returned as JSON.
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_util::future::BoxFuture;
+use http::{
+    HeaderName, HeaderValue, Method, Uri,
+    header::{CONTENT_TYPE, DATE, TE},
+};
+use http_body::Frame;
+use http_body_util::{BodyExt, Full};
+use prost::{Message, bytes::Bytes};
+use prost_reflect::{
+    DescriptorPool, DeserializeOptions, DynamicMessage, ExtensionDescriptor, FieldDescriptor, Kind,
+    MessageDescriptor, MethodDescriptor, ReflectMessage, SerializeOptions,
+};
+use serde_json::{Map, Value as JsonValue, json};
+use tonic::{
+    Code, Status,
+    body::Body as TonicBody,
+    metadata::{GRPC_CONTENT_TYPE, MetadataMap},
+};
+use tower_service::Service;
+
+use crate::{
+    connectrpc::{COMPRESSED_FLAG, Envelopes, Item, envelope, http_status, split_envelope},
+    util::percent_decode,
+};
+
+const HTTP_RULE_EXTENSION: &str = "google.api.http";
+/// The prefix of the request headers forwarded as metadata, and of the response metadata.
+pub const METADATA_HEADER_PREFIX: &str = "grpc-metadata-";
+
+/// A segment of a path template.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    /// `*`, exactly one segment
+    Any,
+    /// `**`, the rest of the path
+    Rest,
+}
+
+/// A variable of a path template, bound to the segments `start..end`.
+#[derive(Clone, Debug)]
+struct Variable {
+    field_path: String,
+    start: usize,
+    end: usize,
+}
+
+/// The path of a HTTP rule, e.g. `/v1/{name=shelves/*/books/*}:publish`.
+#[derive(Clone, Debug)]
+pub struct PathTemplate {
+    segments: Vec<Segment>,
+    variables: Vec<Variable>,
+    verb: Option<String>,
+}
+
+impl PathTemplate {
+    pub fn parse(template: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("invalid path template: `{template}`");
+        let path = template.strip_prefix('/').ok_or_else(invalid)?;
+
+        // the verb is the `:` suffix outside of the variables
+        let mut depth = 0;
+        let mut verb_at = None;
+        for (i, c) in path.char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                ':' if depth == 0 => verb_at = Some(i),
+                _ => {}
+            }
+        }
+        let (path, verb) = match verb_at {
+            Some(i) => (&path[..i], Some(path[i + 1..].to_string())),
+            None => (path, None),
+        };
+
+        let mut segments = Vec::new();
+        let mut variables = Vec::new();
+        for part in split_outside_braces(path) {
+            match part.strip_prefix('{') {
+                Some(var) => {
+                    let var = var.strip_suffix('}').ok_or_else(invalid)?;
+                    let (field_path, sub) = var.split_once('=').unwrap_or((var, "*"));
+                    let start = segments.len();
+                    for x in sub.split('/') {
+                        segments.push(parse_segment(x).ok_or_else(invalid)?);
+                    }
+                    variables.push(Variable {
+                        field_path: field_path.to_string(),
+                        start,
+                        end: segments.len(),
+                    });
+                }
+                None => segments.push(parse_segment(part).ok_or_else(invalid)?),
+            }
+        }
+
+        let rest = segments.iter().position(|x| *x == Segment::Rest);
+        if rest.is_some_and(|i| i + 1 != segments.len()) {
+            anyhow::bail!("`**` must be the last segment of the path template: `{template}`");
+        }
+
+        Ok(Self {
+            segments,
+            variables,
+            verb,
+        })
+    }
+
+    /// Match a request path, return the field paths and the values of the variables.
+    fn matches(&self, path: &str) -> Option<Vec<(&str, String)>> {
+        let path = path.strip_prefix('/')?;
+        let path = match &self.verb {
+            Some(verb) => path.strip_suffix(verb.as_str())?.strip_suffix(':')?,
+            None => path,
+        };
+
+        let parts = path.split('/').collect::<Vec<_>>();
+        let has_rest = self.segments.last() == Some(&Segment::Rest);
+        let fixed = self.segments.len() - usize::from(has_rest);
+        if parts.len() < fixed || (!has_rest && parts.len() != fixed) {
+            return None;
+        }
+
+        for (segment, part) in self.segments.iter().zip(&parts) {
+            match segment {
+                Segment::Literal(x) if percent_decode(part) != *x => return None,
+                Segment::Any if part.is_empty() => return None,
+                _ => {}
+            }
+        }
+
+        let bindings = self
+            .variables
+            .iter()
+            .map(|var| {
+                let end = if has_rest && var.end == self.segments.len() {
+                    parts.len()
+                } else {
+                    var.end
+                };
+                let value = parts[var.start..end]
+                    .iter()
+                    .map(|x| percent_decode(x))
+                    .collect::<Vec<_>>()
+                    .join("/");
+                (var.field_path.as_str(), value)
+            })
+            .collect();
+        Some(bindings)
+    }
+}
+
+fn split_outside_braces(path: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in path.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '/' if depth == 0 => {
+                parts.push(&path[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&path[start..]);
+    parts
+}
+
+fn parse_segment(s: &str) -> Option<Segment> {
+    match s {
+        "*" => Some(Segment::Any),
+        "**" => Some(Segment::Rest),
+        "" => None,
+        _ if s.contains(['{', '}', '=']) => None,
+        _ => Some(Segment::Literal(s.to_string())),
+    }
+}
+
+/// A `google.api.http` binding of a method.
+#[derive(Clone, Debug)]
+pub struct HttpRule {
+    pub method: Method,
+    pub template: PathTemplate,
+    /// the field the request body is mapped to, `*` for the whole request message
+    pub body: Option<String>,
+    /// the field of the response message returned as the response body
+    pub response_body: Option<String>,
+}
+
+impl HttpRule {
+    /// Read the rule and its `additional_bindings` from a `google.api.HttpRule` message.
+    fn from_message(rule: &DynamicMessage) -> anyhow::Result<Vec<Self>> {
+        let string_field = |msg: &DynamicMessage, name: &str| {
+            msg.get_field_by_name(name)
+                .and_then(|x| x.as_str().map(str::to_string))
+                .filter(|x| !x.is_empty())
+        };
+
+        let mut pattern = [
+            ("get", Method::GET),
+            ("put", Method::PUT),
+            ("post", Method::POST),
+            ("delete", Method::DELETE),
+            ("patch", Method::PATCH),
+        ]
+        .into_iter()
+        .find_map(|(name, method)| Some((method, string_field(rule, name)?)));
+        if let Some(custom) = rule.get_field_by_name("custom")
+            && let Some(custom) = custom.as_message()
+            && let (Some(kind), Some(path)) =
+                (string_field(custom, "kind"), string_field(custom, "path"))
+        {
+            pattern = Some((Method::from_bytes(kind.as_bytes())?, path));
+        }
+
+        let mut rules = Vec::new();
+        if let Some((method, path)) = pattern {
+            rules.push(HttpRule {
+                method,
+                template: PathTemplate::parse(&path)?,
+                body: string_field(rule, "body"),
+                response_body: string_field(rule, "response_body"),
+            });
+        }
+
+        if let Some(bindings) = rule.get_field_by_name("additional_bindings")
+            && let Some(bindings) = bindings.as_list()
+        {
+            for x in bindings.iter().filter_map(|x| x.as_message()) {
+                rules.extend(HttpRule::from_message(x)?);
+            }
+        }
+
+        Ok(rules)
+    }
+}
+
+/// A method bound to a HTTP rule.
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub method: MethodDescriptor,
+    pub rule: HttpRule,
+}
+
+impl Route {
+    fn new(method: MethodDescriptor, rule: HttpRule) -> anyhow::Result<Self> {
+        let input = method.input();
+        for var in &rule.template.variables {
+            resolve_field_path(&input, &var.field_path)?;
+        }
+        if let Some(body) = rule.body.as_deref().filter(|x| *x != "*") {
+            resolve_field_path(&input, body)?;
+        }
+        if let Some(response_body) = &rule.response_body {
+            resolve_field_path(&method.output(), response_body)?;
+        }
+
+        Ok(Self { method, rule })
+    }
+
+    /// Build the request message from the path variables, the query string and the body.
+    /// The query parameters are only mapped to the fields not bound by the path or the body.
+    #[allow(clippy::result_large_err)]
+    pub fn request(
+        &self,
+        bindings: Vec<(&str, String)>,
+        query: Option<&str>,
+        body: &[u8],
+    ) -> Result<DynamicMessage, Status> {
+        let input = self.method.input();
+        let body_field = self.rule.body.as_deref();
+
+        let mut root = match body_field {
+            Some(_) if body.iter().all(u8::is_ascii_whitespace) => JsonValue::Object(Map::new()),
+            Some("*") => parse_body(body)?,
+            Some(field) => {
+                JsonValue::Object(Map::from_iter([(field.to_string(), parse_body(body)?)]))
+            }
+            None => JsonValue::Object(Map::new()),
+        };
+        let obj = root
+            .as_object_mut()
+            .ok_or_else(|| Status::invalid_argument("the request body must be a JSON object"))?;
+
+        let mut bound = body_field
+            .map(str::to_string)
+            .into_iter()
+            .collect::<Vec<_>>();
+        for (field_path, value) in bindings {
+            set_field(&input, obj, field_path, value)?;
+            bound.push(field_path.to_string());
+        }
+
+        if body_field != Some("*") {
+            let params = query
+                .unwrap_or_default()
+                .split('&')
+                .filter(|x| !x.is_empty())
+                .map(|x| x.split_once('=').unwrap_or((x, "")))
+                .map(|(k, v)| (decode_query(k), decode_query(v)));
+            for (field_path, value) in params {
+                let is_bound = bound.iter().any(|x| {
+                    field_path == *x
+                        || field_path
+                            .strip_prefix(x.as_str())
+                            .is_some_and(|rest| rest.starts_with('.'))
+                });
+                if !is_bound {
+                    set_field(&input, obj, &field_path, value)?;
+                }
+            }
+        }
+
+        DynamicMessage::deserialize_with_options(input, root, &DeserializeOptions::new())
+            .map_err(|e| Status::invalid_argument(format!("invalid request: {e}")))
+    }
+
+    /// Render the response message, or the field selected by `response_body`, as JSON.
+    #[allow(clippy::result_large_err)]
+    pub fn response(&self, msg: &DynamicMessage) -> Result<JsonValue, Status> {
+        let to_json = |options: &SerializeOptions| {
+            msg.serialize_with_options(serde_json::value::Serializer, options)
+                .map_err(|e| Status::internal(format!("failed to encode JSON: {e}")))
+        };
+
+        let Some(response_body) = &self.rule.response_body else {
+            return to_json(&SerializeOptions::new());
+        };
+
+        let field = resolve_field_path(&msg.descriptor(), response_body)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let mut value = to_json(&SerializeOptions::new())?;
+        if value.get(field.json_name()).is_none() {
+            // the field is still rendered if it has the default value
+            value = to_json(&SerializeOptions::new().skip_default_fields(false))?;
+        }
+        Ok(value
+            .get_mut(field.json_name())
+            .map(JsonValue::take)
+            .unwrap_or_default())
+    }
+}
+
+/// The methods of a descriptor pool reachable with REST requests.
+#[derive(Clone, Debug)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    /// Collect the HTTP rules of all the methods in the pool, client streaming methods are skipped.
+    pub fn new(pool: &DescriptorPool) -> anyhow::Result<Self> {
+        let extension = pool
+            .get_extension_by_name(HTTP_RULE_EXTENSION)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "`{HTTP_RULE_EXTENSION}` is not found, the descriptor set should include `google/api/annotations.proto`"
+                )
+            })?;
+
+        let mut routes = Vec::new();
+        for method in pool
+            .services()
+            .flat_map(|x| x.methods().collect::<Vec<_>>())
+        {
+            routes.extend(method_routes(&method, &extension)?);
+        }
+
+        if routes.is_empty() {
+            anyhow::bail!("no method is annotated with `{HTTP_RULE_EXTENSION}`");
+        }
+
+        Ok(Self { routes })
+    }
+
+    /// The routes of a single method, empty if the method is not annotated.
+    pub fn for_method(method: &MethodDescriptor) -> anyhow::Result<Self> {
+        let routes = match method
+            .parent_pool()
+            .get_extension_by_name(HTTP_RULE_EXTENSION)
+        {
+            Some(extension) => method_routes(method, &extension)?,
+            None => Vec::new(),
+        };
+
+        Ok(Self { routes })
+    }
+
+    /// Find the route of a request, the rules are tried in the order of the descriptor set.
+    pub fn find(&self, method: &Method, path: &str) -> Option<(&Route, Vec<(&str, String)>)> {
+        self.routes
+            .iter()
+            .filter(|x| x.rule.method == method)
+            .find_map(|x| Some((x, x.rule.template.matches(path)?)))
+    }
+}
+
+fn method_routes(
+    method: &MethodDescriptor,
+    extension: &ExtensionDescriptor,
+) -> anyhow::Result<Vec<Route>> {
+    let options = method.options();
+    if !options.has_extension(extension) {
+        return Ok(Vec::new());
+    }
+    if method.is_client_streaming() {
+        eprintln!(
+            "warning: `{}` is skipped, client streaming is not supported",
+            method.full_name()
+        );
+        return Ok(Vec::new());
+    }
+
+    let rule = options.get_extension(extension);
+    let Some(rule) = rule.as_message() else {
+        return Ok(Vec::new());
+    };
+    HttpRule::from_message(rule)?
+        .into_iter()
+        .map(|rule| {
+            Route::new(method.clone(), rule)
+                .map_err(|e| anyhow::anyhow!("invalid HTTP rule of `{}`: {e}", method.full_name()))
+        })
+        .collect()
+}
+
+/// Serve the REST routes of a method with a gRPC service of the method, other requests are
+/// passed through.
+#[derive(Clone, Debug)]
+pub struct TranscodeService<S> {
+    inner: S,
+    router: Arc<Router>,
+    /// the path of the gRPC method served by the inner service
+    path: Uri,
+}
+
+impl<S> TranscodeService<S> {
+    pub fn new(inner: S, router: Router, path: Uri) -> Self {
+        Self {
+            inner,
+            router: Arc::new(router),
+            path,
+        }
+    }
+}
+
+impl<S> Service<http::Request<TonicBody>> for TranscodeService<S>
+where
+    S: Service<http::Request<TonicBody>, Response = http::Response<TonicBody>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = http::Response<TonicBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<TonicBody>) -> Self::Future {
+        if self.router.find(req.method(), req.uri().path()).is_none() {
+            return Box::pin(self.inner.call(req));
+        }
+
+        let router = self.router.clone();
+        let path = self.path.clone();
+        let mut inner = self.inner.clone();
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let Some((route, bindings)) = router.find(&parts.method, parts.uri.path()) else {
+                unreachable!("the route is found before");
+            };
+
+            let req = async {
+                let body = body.collect().await?.to_bytes();
+                let msg = route.request(bindings, parts.uri.query(), &body)?;
+                Ok::<_, Status>(envelope(0, &msg.encode_to_vec()))
+            };
+            let frame = match req.await {
+                Ok(x) => x,
+                Err(status) => return Ok(error_response(&status)),
+            };
+
+            let mut req = http::Request::new(TonicBody::new(Full::new(frame)));
+            *req.method_mut() = Method::POST;
+            *req.uri_mut() = path;
+            let headers = req.headers_mut();
+            headers.insert(CONTENT_TYPE, GRPC_CONTENT_TYPE);
+            headers.insert(TE, HeaderValue::from_static("trailers"));
+
+            let resp = inner.call(req).await?;
+            Ok(serve_response(resp, route.clone())
+                .await
+                .unwrap_or_else(|status| error_response(&status)))
+        })
+    }
+}
+
+/// Translate the gRPC response to JSON, the messages of a server streaming call are written as
+/// newline-delimited JSON.
+#[allow(clippy::result_large_err)]
+async fn serve_response(
+    resp: http::Response<TonicBody>,
+    route: Route,
+) -> Result<http::Response<TonicBody>, Status> {
+    let (parts, body) = resp.into_parts();
+    if let Some(status) = Status::from_header_map(&parts.headers)
+        && status.code() != Code::Ok
+    {
+        return Err(status);
+    }
+    let output = route.method.output();
+    let streaming = route.method.is_server_streaming();
+    let decode = move |flags: u8, data: Bytes| {
+        if flags & COMPRESSED_FLAG != 0 {
+            return Err(Status::internal("compressed responses are not supported"));
+        }
+        let msg = DynamicMessage::decode(output.clone(), data)
+            .map_err(|e| Status::internal(format!("invalid response: {e}")))?;
+        route.response(&msg)
+    };
+
+    if !streaming {
+        let collected = body.collect().await?;
+        if let Some(status) = collected.trailers().and_then(Status::from_header_map)
+            && status.code() != Code::Ok
+        {
+            return Err(status);
+        }
+        let data = collected.to_bytes();
+        let (flags, data) = split_envelope(&data)
+            .ok_or_else(|| Status::internal("the response message is missing"))?;
+        let body = serde_json::to_vec(&decode(flags, data)?)
+            .map_err(|e| Status::internal(e.to_string()))?;
+        return Ok(json_response(
+            &MetadataMap::new(),
+            TonicBody::new(Full::new(Bytes::from(body))),
+        ));
+    }
+
+    let body = Envelopes::new(body, move |item| match item {
+        Item::Message(flags, data) => Ok(Some(Frame::data(stream_line(decode(flags, data))))),
+        Item::Trailers(trailers) => Ok(Status::from_header_map(&trailers)
+            .filter(|x| x.code() != Code::Ok)
+            .map(|status| Frame::data(stream_line(Err(status))))),
+        Item::End => Ok(None),
+    });
+    Ok(json_response(&MetadataMap::new(), TonicBody::new(body)))
+}
+
+/// A line of a newline-delimited JSON response of a server streaming call.
+pub fn stream_line(result: Result<JsonValue, Status>) -> Bytes {
+    let line = match result {
+        Ok(result) => json!({ "result": result }),
+        Err(status) => json!({ "error": status_json(&status) }),
+    };
+    let mut line = serde_json::to_vec(&line).unwrap_or_default();
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+/// A JSON response, the metadata are returned as the `grpc-metadata-*` headers.
+pub fn json_response(metadata: &MetadataMap, body: TonicBody) -> http::Response<TonicBody> {
+    let mut resp = http::Response::new(body);
+    let headers = resp.headers_mut();
+    for (key, value) in metadata.clone().into_headers().iter() {
+        if key == CONTENT_TYPE || key == DATE || key.as_str().starts_with("grpc-") {
+            continue;
+        }
+        if let Ok(key) = HeaderName::try_from(format!("{METADATA_HEADER_PREFIX}{key}")) {
+            headers.append(key, value.clone());
+        }
+    }
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    resp
+}
+
+/// The response of a failed call, with the HTTP status mapped from the gRPC status code.
+pub fn error_response(status: &Status) -> http::Response<TonicBody> {
+    let body = serde_json::to_vec(&status_json(status)).unwrap_or_default();
+    let mut resp = json_response(
+        status.metadata(),
+        TonicBody::new(Full::new(Bytes::from(body))),
+    );
+    *resp.status_mut() = http_status(status.code());
+    resp
+}
+
+/// The error body of a failed call, a `google.rpc.Status` in JSON.
+pub fn status_json(status: &Status) -> JsonValue {
+    json!({
+        "code": status.code() as i32,
+        "message": status.message(),
+        "details": [],
+    })
+}
+
+#[allow(clippy::result_large_err)]
+fn parse_body(body: &[u8]) -> Result<JsonValue, Status> {
+    serde_json::from_slice(body)
+        .map_err(|e| Status::invalid_argument(format!("invalid request body: {e}")))
+}
+
+/// Query parameters are form encoded, `+` is a space.
+fn decode_query(s: &str) -> String {
+    percent_decode(&s.replace('+', " "))
+}
+
+fn find_field(desc: &MessageDescriptor, name: &str) -> Option<FieldDescriptor> {
+    desc.get_field_by_name(name)
+        .or_else(|| desc.get_field_by_json_name(name))
+}
+
+/// Find the field of a dotted path, e.g. `book.author.name`.
+fn resolve_field_path(desc: &MessageDescriptor, path: &str) -> anyhow::Result<FieldDescriptor> {
+    let (name, rest) = match path.split_once('.') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (path, None),
+    };
+    let field = find_field(desc, name)
+        .ok_or_else(|| anyhow::anyhow!("field `{name}` not found in `{}`", desc.full_name()))?;
+
+    match (rest, field.kind()) {
+        (None, _) => Ok(field),
+        (Some(rest), Kind::Message(sub)) if !field.is_list() && !field.is_map() => {
+            resolve_field_path(&sub, rest)
+        }
+        (Some(_), _) => anyhow::bail!("field `{name}` is not a singular message"),
+    }
+}
+
+/// Set the field of a dotted path in the JSON object of a message. The value is kept as a
+/// string, which the JSON mapping accepts for all the scalar types but `bool`.
+#[allow(clippy::result_large_err)]
+fn set_field(
+    desc: &MessageDescriptor,
+    obj: &mut Map<String, JsonValue>,
+    path: &str,
+    value: String,
+) -> Result<(), Status> {
+    let (name, rest) = match path.split_once('.') {
+        Some((name, rest)) => (name, Some(rest)),
+        None => (path, None),
+    };
+    let field = find_field(desc, name).ok_or_else(|| {
+        Status::invalid_argument(format!(
+            "field `{name}` not found in `{}`",
+            desc.full_name()
+        ))
+    })?;
+    if field.is_map() {
+        return Err(Status::invalid_argument(format!(
+            "map field `{name}` can't be set from the path or the query"
+        )));
+    }
+
+    // a `body: "*"` may have set the field by its JSON name, keep a single key
+    if field.json_name() != field.name()
+        && let Some(x) = obj.remove(field.json_name())
+    {
+        obj.entry(field.name()).or_insert(x);
+    }
+    let entry = obj.entry(field.name());
+    match (rest, field.kind()) {
+        (Some(rest), Kind::Message(sub)) if !field.is_list() => {
+            match entry.or_insert_with(|| JsonValue::Object(Map::new())) {
+                JsonValue::Object(sub_obj) => set_field(&sub, sub_obj, rest, value),
+                _ => Err(Status::invalid_argument(format!(
+                    "field `{name}` is not an object"
+                ))),
+            }
+        }
+        (Some(_), _) => Err(Status::invalid_argument(format!(
+            "field `{name}` is not a singular message"
+        ))),
+        (None, kind) => {
+            let value = scalar_json(&kind, value)?;
+            if !field.is_list() {
+                *entry.or_insert(JsonValue::Null) = value;
+                return Ok(());
+            }
+            match entry.or_insert_with(|| JsonValue::Array(Vec::new())) {
+                JsonValue::Array(x) => {
+                    x.push(value);
+                    Ok(())
+                }
+                _ => Err(Status::invalid_argument(format!(
+                    "field `{name}` is not an array"
+                ))),
+            }
+        }
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn scalar_json(kind: &Kind, value: String) -> Result<JsonValue, Status> {
+    let is_bool = match kind {
+        Kind::Bool => true,
+        Kind::Message(x) => x.full_name() == "google.protobuf.BoolValue",
+        _ => false,
+    };
+    if !is_bool {
+        return Ok(JsonValue::String(value));
+    }
+
+    value
+        .parse::<bool>()
+        .map(JsonValue::Bool)
+        .map_err(|_| Status::invalid_argument(format!("invalid bool: {value}")))
+}
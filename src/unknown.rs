@@ -0,0 +1,161 @@
+//! Reporting of the fields missing from the schema, usually caused by a stale descriptor set.
+
+use std::str::FromStr;
+
+use prost::encoding::WireType;
+use prost_reflect::{DynamicMessage, MapKey, ReflectMessage, Value};
+
+use crate::{raw::decode_raw, util::join_path};
+
+/// The key of the unknown fields when rendered in JSON.
+const UNKNOWN_FIELDS_KEY: &str = "@unknownFields";
+
+/// What to do when a decoded message contains unknown fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnknownFieldsMode {
+    #[default]
+    Ignore,
+    Warn,
+    Error,
+}
+
+impl FromStr for UnknownFieldsMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(UnknownFieldsMode::Ignore),
+            "warn" => Ok(UnknownFieldsMode::Warn),
+            "error" => Ok(UnknownFieldsMode::Error),
+            _ => Err(anyhow::anyhow!(
+                "unknown mode, expect `ignore`, `warn` or `error`"
+            )),
+        }
+    }
+}
+
+impl UnknownFieldsMode {
+    /// Print the unknown fields to stderr, or fail, according to the mode.
+    pub fn check(self, msg: &DynamicMessage) -> anyhow::Result<()> {
+        if self == UnknownFieldsMode::Ignore {
+            return Ok(());
+        }
+
+        let mut fields = Vec::new();
+        collect_message("", msg, &mut fields);
+        if fields.is_empty() {
+            return Ok(());
+        }
+
+        let report = format!(
+            "unknown fields in `{}`: {}",
+            msg.descriptor().full_name(),
+            fields.join(", ")
+        );
+
+        match self {
+            UnknownFieldsMode::Error => anyhow::bail!(report),
+            _ => eprintln!("warning: {report}"),
+        }
+
+        Ok(())
+    }
+}
+
+/// Add the unknown fields to the JSON serialized message, under the `@unknownFields` key
+/// of the object they belong to, in the same layout as the `raw` mode of `json`.
+pub fn render_unknown_fields(msg: &DynamicMessage, json: &mut serde_json::Value) {
+    let serde_json::Value::Object(map) = json else {
+        return;
+    };
+
+    let unknown = msg
+        .unknown_fields()
+        .filter_map(|x| {
+            let mut buf = Vec::new();
+            x.encode(&mut buf);
+            match decode_raw(&buf) {
+                Ok(serde_json::Value::Array(mut fields)) if fields.len() == 1 => fields.pop(),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        map.insert(UNKNOWN_FIELDS_KEY.to_string(), unknown.into());
+    }
+
+    for (field, value) in msg.fields() {
+        let Some(child) = map.get_mut(field.json_name()) else {
+            continue;
+        };
+
+        match (value, child) {
+            (Value::Message(m), child) => render_unknown_fields(m, child),
+            (Value::List(values), serde_json::Value::Array(children)) => {
+                for (v, child) in values.iter().zip(children) {
+                    if let Value::Message(m) = v {
+                        render_unknown_fields(m, child);
+                    }
+                }
+            }
+            (Value::Map(values), serde_json::Value::Object(children)) => {
+                for (k, v) in values {
+                    if let (Value::Message(m), Some(child)) = (v, children.get_mut(&map_key(k))) {
+                        render_unknown_fields(m, child);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn map_key(key: &MapKey) -> String {
+    match key {
+        MapKey::Bool(x) => x.to_string(),
+        MapKey::I32(x) => x.to_string(),
+        MapKey::I64(x) => x.to_string(),
+        MapKey::U32(x) => x.to_string(),
+        MapKey::U64(x) => x.to_string(),
+        MapKey::String(x) => x.clone(),
+    }
+}
+
+fn collect_message(path: &str, msg: &DynamicMessage, fields: &mut Vec<String>) {
+    for field in msg.unknown_fields() {
+        let wire_type = match field.wire_type() {
+            WireType::Varint => "varint",
+            WireType::SixtyFourBit => "fixed64",
+            WireType::LengthDelimited => "len",
+            WireType::StartGroup | WireType::EndGroup => "group",
+            WireType::ThirtyTwoBit => "fixed32",
+        };
+        fields.push(format!(
+            "{} ({wire_type})",
+            join_path(path, &field.number().to_string())
+        ));
+    }
+
+    for (field, value) in msg.fields() {
+        let field_path = join_path(path, field.name());
+
+        match value {
+            Value::Message(m) => collect_message(&field_path, m, fields),
+            Value::List(values) => {
+                for (i, v) in values.iter().enumerate() {
+                    if let Value::Message(m) = v {
+                        collect_message(&format!("{field_path}[{i}]"), m, fields);
+                    }
+                }
+            }
+            Value::Map(values) => {
+                for (k, v) in values {
+                    if let Value::Message(m) = v {
+                        collect_message(&format!("{field_path}[{}]", map_key(k)), m, fields);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
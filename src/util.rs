@@ -1,6 +1,157 @@
-pub fn new_tokio_rt() -> tokio::runtime::Runtime {
-    tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .unwrap()
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use tonic::{Code, codec::CompressionEncoding};
+
+/// Build a single-threaded runtime, or a multi-threaded one with the number of worker threads.
+pub fn new_tokio_rt(worker_threads: Option<usize>) -> tokio::runtime::Runtime {
+    let mut builder = match worker_threads {
+        Some(n) => {
+            let mut builder = tokio::runtime::Builder::new_multi_thread();
+            builder.worker_threads(n);
+            builder
+        }
+        None => tokio::runtime::Builder::new_current_thread(),
+    };
+    builder.enable_all().build().unwrap()
+}
+
+pub fn parse_worker_threads(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("the number of worker threads must be positive".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub fn parse_compression_encoding(s: &str) -> Result<CompressionEncoding, String> {
+    match s {
+        "gzip" => Ok(CompressionEncoding::Gzip),
+        "zstd" => Ok(CompressionEncoding::Zstd),
+        "deflate" => Ok(CompressionEncoding::Deflate),
+        _ => Err("unknown compression, expect `gzip`, `zstd` or `deflate`".to_string()),
+    }
+}
+
+/// Parse a byte size, e.g. `1048576`, `512KiB`, `16MiB`. `K`, `M` and `G` are treated as binary units.
+pub fn parse_byte_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("unknown size unit: {unit}")),
+    };
+
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|x| x.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size: {s}"))
+}
+
+/// Parse a HTTP/2 flow control window size, e.g. `1MiB`.
+pub fn parse_window_size(s: &str) -> Result<u32, String> {
+    let size = parse_byte_size(s)?;
+    u32::try_from(size)
+        .ok()
+        .filter(|x| *x < 1 << 31)
+        .ok_or_else(|| format!("the window size must be less than 2GiB: {s}"))
+}
+
+/// Parse a duration, e.g. `500ms`, `1.5s`, `2m` and `1h`. A plain number is in seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let multiplier = match unit.trim() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("unknown duration unit: {unit}")),
+    };
+
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|x| Duration::try_from_secs_f64(x * multiplier).ok())
+        .ok_or_else(|| format!("invalid duration: {s}"))
+}
+
+/// Parse a status code, by its name like `UNAVAILABLE` and `unavailable`, or its number.
+pub fn parse_code(s: &str) -> Result<Code, String> {
+    if let Ok(n) = s.parse::<i32>() {
+        return match Code::from_i32(n) {
+            Code::Unknown if n != Code::Unknown as i32 => Err(format!("unknown status code: {s}")),
+            code => Ok(code),
+        };
+    }
+
+    let code = match s.to_ascii_uppercase().replace('-', "_").as_str() {
+        "OK" => Code::Ok,
+        "CANCELLED" => Code::Cancelled,
+        "UNKNOWN" => Code::Unknown,
+        "INVALID_ARGUMENT" => Code::InvalidArgument,
+        "DEADLINE_EXCEEDED" => Code::DeadlineExceeded,
+        "NOT_FOUND" => Code::NotFound,
+        "ALREADY_EXISTS" => Code::AlreadyExists,
+        "PERMISSION_DENIED" => Code::PermissionDenied,
+        "RESOURCE_EXHAUSTED" => Code::ResourceExhausted,
+        "FAILED_PRECONDITION" => Code::FailedPrecondition,
+        "ABORTED" => Code::Aborted,
+        "OUT_OF_RANGE" => Code::OutOfRange,
+        "UNIMPLEMENTED" => Code::Unimplemented,
+        "INTERNAL" => Code::Internal,
+        "UNAVAILABLE" => Code::Unavailable,
+        "DATA_LOSS" => Code::DataLoss,
+        "UNAUTHENTICATED" => Code::Unauthenticated,
+        _ => return Err(format!("unknown status code: {s}")),
+    };
+    Ok(code)
+}
+
+/// Decode the `%XX` escapes, invalid escapes are kept as is.
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(b) = s
+                .get(i + 1..i + 3)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+        {
+            out.push(b);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Append the field name to the dotted path of its parent message.
+pub fn join_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{path}.{name}")
+    }
+}
+
+/// Lock the mutex, ignoring the poisoning. None of the guarded values can be left
+/// half updated by a panic.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
 }
@@ -0,0 +1,646 @@
+//! Client side checks of outgoing messages.
+//!
+//! Besides the proto2 `required` fields, a subset of the field constraints of
+//! [`buf.validate`](https://github.com/bufbuild/protovalidate) and
+//! [`protoc-gen-validate`](https://github.com/bufbuild/protoc-gen-validate) is supported:
+//! `required`, `const`, `lt`, `lte`, `gt`, `gte`, `in`, `not_in`, the length rules of strings,
+//! bytes, repeated and map fields, `pattern`, `prefix`, `suffix`, `contains`, `not_contains`
+//! and `defined_only`. Other rules, including CEL expressions, are ignored.
+//! The constraints are only checked if the validate proto files are in the descriptor set.
+
+use std::cmp::Ordering;
+
+use prost_reflect::{Cardinality, DynamicMessage, FieldDescriptor, Kind, ReflectMessage, Value};
+
+use crate::util::join_path;
+
+const BUF_VALIDATE_FIELD: &str = "buf.validate.field";
+const BUF_VALIDATE_ONEOF: &str = "buf.validate.oneof";
+const PGV_FIELD: &str = "validate.rules";
+const PGV_ONEOF: &str = "validate.required";
+
+/// Which checks to run on an outgoing message.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Validation {
+    /// proto2 `required` fields must be set
+    pub required: bool,
+    /// `buf.validate` and `protoc-gen-validate` field constraints
+    pub constraints: bool,
+}
+
+impl Validation {
+    pub fn is_enabled(&self) -> bool {
+        self.required || self.constraints
+    }
+
+    /// Check the message, return the violations prefixed with the field path.
+    pub fn check(&self, msg: &DynamicMessage) -> Vec<String> {
+        let mut violations = Vec::new();
+        if self.is_enabled() {
+            self.check_message("", msg, &mut violations);
+        }
+        violations
+    }
+
+    fn check_message(&self, path: &str, msg: &DynamicMessage, violations: &mut Vec<String>) {
+        let desc = msg.descriptor();
+        let pool = desc.parent_pool();
+        let field_rules = [BUF_VALIDATE_FIELD, PGV_FIELD]
+            .into_iter()
+            .filter_map(|x| pool.get_extension_by_name(x))
+            .collect::<Vec<_>>();
+
+        if self.constraints {
+            for oneof in desc.oneofs() {
+                let options = oneof.options();
+                let required = [BUF_VALIDATE_ONEOF, PGV_ONEOF]
+                    .into_iter()
+                    .filter_map(|x| pool.get_extension_by_name(x))
+                    .filter(|x| options.has_extension(x))
+                    .any(|x| match options.get_extension(&x).as_ref() {
+                        Value::Bool(b) => *b,
+                        Value::Message(m) => get_bool(m, "required"),
+                        _ => false,
+                    });
+
+                if required && !oneof.fields().any(|x| msg.has_field(&x)) {
+                    violations.push(format!(
+                        "{}: exactly one field is required in oneof",
+                        join_path(path, oneof.name())
+                    ));
+                }
+            }
+        }
+
+        for field in desc.fields() {
+            let field_path = join_path(path, field.name());
+            let value = msg.get_field(&field);
+
+            if self.required
+                && field.cardinality() == Cardinality::Required
+                && !msg.has_field(&field)
+            {
+                violations.push(format!("{field_path}: required field is missing"));
+            }
+
+            if self.constraints {
+                let options = field.options();
+                for ext in &field_rules {
+                    if options.has_extension(ext)
+                        && let Value::Message(rules) = options.get_extension(ext).as_ref()
+                    {
+                        check_field_rules(&field_path, msg, &field, &value, rules, violations);
+                    }
+                }
+            }
+
+            match value.as_ref() {
+                Value::Message(m) if msg.has_field(&field) => {
+                    self.check_message(&field_path, m, violations)
+                }
+                Value::List(values) => {
+                    for (i, v) in values.iter().enumerate() {
+                        if let Value::Message(m) = v {
+                            self.check_message(&format!("{field_path}[{i}]"), m, violations);
+                        }
+                    }
+                }
+                Value::Map(values) => {
+                    for (k, v) in values {
+                        if let Value::Message(m) = v {
+                            let key = Value::from(k.clone());
+                            self.check_message(
+                                &format!("{field_path}[{}]", display(&key)),
+                                m,
+                                violations,
+                            );
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn get_bool(msg: &DynamicMessage, name: &str) -> bool {
+    msg.get_field_by_name(name)
+        .is_some_and(|x| matches!(x.as_ref(), Value::Bool(true)))
+}
+
+fn check_field_rules(
+    path: &str,
+    msg: &DynamicMessage,
+    field: &FieldDescriptor,
+    value: &Value,
+    rules: &DynamicMessage,
+    violations: &mut Vec<String>,
+) {
+    if get_bool(rules, "required") && !msg.has_field(field) {
+        violations.push(format!("{path}: value is required"));
+    }
+
+    for (rule_field, rule_value) in rules.fields() {
+        let Value::Message(type_rules) = rule_value else {
+            continue;
+        };
+
+        match rule_field.name() {
+            "repeated" => {
+                let Value::List(values) = value else {
+                    continue;
+                };
+                check_count(path, values.len(), type_rules, "items", violations);
+
+                if let Some(Value::Message(item_rules)) = type_rules
+                    .get_field_by_name("items")
+                    .as_deref()
+                    .filter(|_| type_rules.has_field_by_name("items"))
+                {
+                    for (i, v) in values.iter().enumerate() {
+                        let item_path = format!("{path}[{i}]");
+                        for (_, item_type_rules) in item_rules.fields() {
+                            if let Value::Message(item_type_rules) = item_type_rules {
+                                check_scalar_rules(
+                                    &item_path,
+                                    field,
+                                    v,
+                                    item_type_rules,
+                                    violations,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            "map" => {
+                if let Value::Map(values) = value {
+                    check_count(path, values.len(), type_rules, "pairs", violations);
+                }
+            }
+            "message" => {
+                if get_bool(type_rules, "required") && !msg.has_field(field) {
+                    violations.push(format!("{path}: value is required"));
+                }
+            }
+            // the rules of a message field like `duration` only apply when it is set
+            _ if field.kind().as_message().is_some() && !msg.has_field(field) => continue,
+            _ => match value {
+                Value::List(values) => {
+                    for (i, v) in values.iter().enumerate() {
+                        let item_path = format!("{path}[{i}]");
+                        check_scalar_rules(&item_path, field, v, type_rules, violations);
+                    }
+                }
+                v => check_scalar_rules(path, field, v, type_rules, violations),
+            },
+        }
+    }
+}
+
+fn check_count(
+    path: &str,
+    count: usize,
+    rules: &DynamicMessage,
+    unit: &str,
+    violations: &mut Vec<String>,
+) {
+    let count = count as u64;
+
+    for (rule_field, rule_value) in rules.fields() {
+        let Some(limit) = rule_value.as_u64() else {
+            continue;
+        };
+
+        let name = rule_field.name();
+        if (name == format!("min_{unit}") && count < limit)
+            || (name == format!("max_{unit}") && count > limit)
+        {
+            violations.push(format!("{path}: {name} is {limit}, got {count} {unit}"));
+        }
+    }
+}
+
+fn check_scalar_rules(
+    path: &str,
+    field: &FieldDescriptor,
+    value: &Value,
+    rules: &DynamicMessage,
+    violations: &mut Vec<String>,
+) {
+    // a lower bound above the upper bound is an exclusive range, the value must be outside of it
+    let bound = |names: [&str; 2]| rules.fields().find(|(x, _)| names.contains(&x.name()));
+    let exclusive = match (bound(["gt", "gte"]), bound(["lt", "lte"])) {
+        (Some((lower_field, lower)), Some((upper_field, upper)))
+            if compare(lower, upper).is_some_and(Ordering::is_gt) =>
+        {
+            if let (Some(above), Some(below)) = (compare(value, lower), compare(value, upper))
+                && !in_bound(lower_field.name(), above)
+                && !in_bound(upper_field.name(), below)
+            {
+                violations.push(format!(
+                    "{path}: {} is {} or {} is {}, got {}",
+                    lower_field.name(),
+                    display(lower),
+                    upper_field.name(),
+                    display(upper),
+                    display(value)
+                ));
+            }
+            true
+        }
+        _ => false,
+    };
+
+    for (rule_field, rule_value) in rules.fields() {
+        let name = rule_field.name();
+
+        let ok = match name {
+            "lt" | "lte" | "gt" | "gte" if exclusive => continue,
+            "const" | "lt" | "lte" | "gt" | "gte" => {
+                let Some(ordering) = compare(value, rule_value) else {
+                    continue;
+                };
+                in_bound(name, ordering)
+            }
+            "in" | "not_in" => {
+                let Value::List(candidates) = rule_value else {
+                    continue;
+                };
+                let orderings = candidates
+                    .iter()
+                    .filter_map(|x| compare(value, x))
+                    .collect::<Vec<_>>();
+                if orderings.is_empty() && !candidates.is_empty() {
+                    continue;
+                }
+                orderings.iter().any(|x| x.is_eq()) == (name == "in")
+            }
+            "len" | "min_len" | "max_len" | "len_bytes" | "min_bytes" | "max_bytes" => {
+                let (Some(limit), Some(len)) = (rule_value.as_u64(), length(value, name)) else {
+                    continue;
+                };
+                match name {
+                    "len" | "len_bytes" => len == limit,
+                    "min_len" | "min_bytes" => len >= limit,
+                    _ => len <= limit,
+                }
+            }
+            "pattern" | "prefix" | "suffix" | "contains" | "not_contains" => {
+                let (Some(s), Some(rule)) = (as_text(value), as_text(rule_value)) else {
+                    continue;
+                };
+                match name {
+                    "pattern" => match regex_lite::Regex::new(&rule) {
+                        Ok(re) => re.is_match(&s),
+                        Err(_) => continue,
+                    },
+                    "prefix" => s.starts_with(&*rule),
+                    "suffix" => s.ends_with(&*rule),
+                    "contains" => s.contains(&*rule),
+                    _ => !s.contains(&*rule),
+                }
+            }
+            "defined_only" => {
+                let (Value::Bool(true), Kind::Enum(e), Value::EnumNumber(n)) =
+                    (rule_value, field.kind(), value)
+                else {
+                    continue;
+                };
+                e.get_value(*n).is_some()
+            }
+            _ => continue,
+        };
+
+        if !ok {
+            violations.push(format!(
+                "{path}: {name} is {}, got {}",
+                display(rule_value),
+                display(value)
+            ));
+        }
+    }
+}
+
+/// Whether the ordering of the value against the rule value satisfies the comparison rule.
+fn in_bound(rule: &str, ordering: Ordering) -> bool {
+    match rule {
+        "const" => ordering.is_eq(),
+        "lt" => ordering.is_lt(),
+        "lte" => ordering.is_le(),
+        "gt" => ordering.is_gt(),
+        _ => ordering.is_ge(),
+    }
+}
+
+fn length(value: &Value, rule: &str) -> Option<u64> {
+    let len = match value {
+        Value::String(s) if rule.ends_with("bytes") => s.len(),
+        Value::String(s) => s.chars().count(),
+        Value::Bytes(b) => b.len(),
+        _ => return None,
+    };
+    Some(len as u64)
+}
+
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Bytes(b) => Some(String::from_utf8_lossy(b).into_owned()),
+        _ => None,
+    }
+}
+
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+fn as_number(value: &Value) -> Option<Number> {
+    Some(match value {
+        Value::I32(x) | Value::EnumNumber(x) => Number::Int(i128::from(*x)),
+        Value::I64(x) => Number::Int(i128::from(*x)),
+        Value::U32(x) => Number::Int(i128::from(*x)),
+        Value::U64(x) => Number::Int(i128::from(*x)),
+        Value::F32(x) => Number::Float(f64::from(*x)),
+        Value::F64(x) => Number::Float(*x),
+        // `google.protobuf.Duration` and `google.protobuf.Timestamp`, in nanoseconds
+        Value::Message(m) if is_time(m) => {
+            let seconds = m.get_field_by_name("seconds")?.as_i64()?;
+            let nanos = m.get_field_by_name("nanos")?.as_i32()?;
+            Number::Int(i128::from(seconds) * 1_000_000_000 + i128::from(nanos))
+        }
+        _ => return None,
+    })
+}
+
+/// Compare the field value with the rule value, `None` if they are not comparable.
+fn compare(value: &Value, rule: &Value) -> Option<Ordering> {
+    match (as_number(value), as_number(rule)) {
+        (Some(Number::Int(a)), Some(Number::Int(b))) => Some(a.cmp(&b)),
+        (Some(Number::Float(a)), Some(Number::Float(b))) => a.partial_cmp(&b),
+        (Some(Number::Int(a)), Some(Number::Float(b))) => (a as f64).partial_cmp(&b),
+        (Some(Number::Float(a)), Some(Number::Int(b))) => a.partial_cmp(&(b as f64)),
+        _ => match (value, rule) {
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+            _ => None,
+        },
+    }
+}
+
+fn is_time(msg: &DynamicMessage) -> bool {
+    matches!(
+        msg.descriptor().full_name(),
+        "google.protobuf.Duration" | "google.protobuf.Timestamp"
+    )
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{s:?}"),
+        Value::Bytes(b) => format!("{:?}", String::from_utf8_lossy(b)),
+        Value::List(values) => format!(
+            "[{}]",
+            values.iter().map(display).collect::<Vec<_>>().join(", ")
+        ),
+        Value::Bool(x) => x.to_string(),
+        Value::I32(x) | Value::EnumNumber(x) => x.to_string(),
+        Value::I64(x) => x.to_string(),
+        Value::U32(x) => x.to_string(),
+        Value::U64(x) => x.to_string(),
+        Value::F32(x) => x.to_string(),
+        Value::F64(x) => x.to_string(),
+        Value::Message(m) if is_time(m) => {
+            serde_json::to_string(m).unwrap_or_else(|_| format!("{m:?}"))
+        }
+        v => format!("{v:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protox::{
+        Compiler,
+        file::{ChainFileResolver, File, FileResolver, GoogleFileResolver},
+    };
+
+    use super::*;
+
+    /// A trimmed down `buf/validate/validate.proto`, with the rules used by the tests.
+    const VALIDATE_PROTO: &str = r#"
+        syntax = "proto2";
+        package buf.validate;
+        import "google/protobuf/descriptor.proto";
+        import "google/protobuf/duration.proto";
+        import "google/protobuf/timestamp.proto";
+
+        extend google.protobuf.FieldOptions { optional FieldRules field = 1159; }
+
+        message FieldRules {
+          optional bool required = 25;
+          oneof type {
+            Int32Rules int32 = 3;
+            StringRules string = 14;
+            EnumRules enum = 16;
+            RepeatedRules repeated = 18;
+            DurationRules duration = 21;
+            TimestampRules timestamp = 22;
+          }
+        }
+        message Int32Rules {
+          optional int32 const = 1;
+          optional int32 lt = 2;
+          optional int32 lte = 3;
+          optional int32 gt = 4;
+          optional int32 gte = 5;
+          repeated int32 in = 6;
+          repeated int32 not_in = 7;
+        }
+        message StringRules {
+          optional string const = 1;
+          optional uint64 min_len = 2;
+          optional uint64 max_len = 3;
+          optional string pattern = 6;
+          optional string prefix = 7;
+          repeated string in = 10;
+          repeated string not_in = 11;
+        }
+        message EnumRules { optional bool defined_only = 2; }
+        message RepeatedRules {
+          optional uint64 min_items = 1;
+          optional uint64 max_items = 2;
+          optional FieldRules items = 4;
+        }
+        message DurationRules {
+          optional google.protobuf.Duration const = 2;
+          optional google.protobuf.Duration lt = 3;
+          optional google.protobuf.Duration gte = 6;
+          repeated google.protobuf.Duration in = 7;
+        }
+        message TimestampRules {
+          optional google.protobuf.Timestamp gt = 5;
+          optional bool lt_now = 7;
+        }
+    "#;
+
+    const TEST_PROTO: &str = r#"
+        syntax = "proto3";
+        package test;
+        import "buf/validate/validate.proto";
+        import "google/protobuf/duration.proto";
+        import "google/protobuf/timestamp.proto";
+
+        enum Color { RED = 0; GREEN = 1; }
+
+        message Req {
+          int32 age = 1 [(buf.validate.field).int32 = {gte: 0, lt: 150}];
+          int32 level = 2 [(buf.validate.field).int32 = {in: [1, 2, 3]}];
+          string name = 3 [(buf.validate.field).string = {min_len: 2, pattern: "^[a-z]+$"}];
+          string kind = 4 [(buf.validate.field).string = {not_in: ["admin"]}];
+          Color color = 5 [(buf.validate.field).enum.defined_only = true];
+          repeated string tags = 6 [(buf.validate.field).repeated = {
+            max_items: 2, items: {string: {prefix: "t"}}
+          }];
+          google.protobuf.Duration ttl = 7 [(buf.validate.field).duration = {
+            gte: {seconds: 1}, lt: {seconds: 60}
+          }];
+          google.protobuf.Duration interval = 8 [(buf.validate.field).duration = {
+            in: [{seconds: 1}, {nanos: 500000000}]
+          }];
+          google.protobuf.Timestamp at = 9 [(buf.validate.field).timestamp = {
+            gt: {seconds: 1000}, lt_now: true
+          }];
+          int32 score = 10 [(buf.validate.field).int32 = {gt: 10, lt: 5}];
+        }
+    "#;
+
+    struct Sources;
+
+    impl FileResolver for Sources {
+        fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+            match name {
+                "buf/validate/validate.proto" => File::from_source(name, VALIDATE_PROTO),
+                "test.proto" => File::from_source(name, TEST_PROTO),
+                _ => Err(protox::Error::file_not_found(name)),
+            }
+        }
+    }
+
+    fn check(json: &str) -> Vec<String> {
+        let mut resolver = ChainFileResolver::new();
+        resolver.add(Sources);
+        resolver.add(GoogleFileResolver::new());
+        let mut compiler = Compiler::with_file_resolver(resolver);
+        compiler.open_file("test.proto").unwrap();
+        let desc = compiler
+            .descriptor_pool()
+            .get_message_by_name("test.Req")
+            .unwrap();
+
+        let mut de = serde_json::Deserializer::from_str(json);
+        let msg = DynamicMessage::deserialize(desc, &mut de).unwrap();
+        Validation {
+            required: true,
+            constraints: true,
+        }
+        .check(&msg)
+    }
+
+    const VALID: &str = r#""level": 1, "name": "bob""#;
+
+    #[test]
+    fn valid_message() {
+        assert_eq!(check(&format!("{{{VALID}}}")), Vec::<String>::new());
+        assert_eq!(
+            check(&format!(
+                r#"{{{VALID}, "age": 149, "tags": ["t1", "t2"], "ttl": "1s",
+                "interval": "0.5s", "at": "2020-01-01T00:00:00Z"}}"#
+            )),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            check(&format!(r#"{{{VALID}, "age": -1}}"#)),
+            ["age: gte is 0, got -1"]
+        );
+        assert_eq!(
+            check(&format!(r#"{{{VALID}, "age": 150}}"#)),
+            ["age: lt is 150, got 150"]
+        );
+        assert_eq!(
+            check(r#"{"level": 4, "name": "bob"}"#),
+            ["level: in is [1, 2, 3], got 4"]
+        );
+    }
+
+    #[test]
+    fn exclusive_range() {
+        for score in [4, 11, -1] {
+            assert_eq!(
+                check(&format!(r#"{{{VALID}, "score": {score}}}"#)),
+                Vec::<String>::new()
+            );
+        }
+        for score in [5, 7, 10] {
+            assert_eq!(
+                check(&format!(r#"{{{VALID}, "score": {score}}}"#)),
+                [format!("score: gt is 10 or lt is 5, got {score}")]
+            );
+        }
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            check(r#"{"level": 1, "name": "B"}"#),
+            [
+                r#"name: min_len is 2, got "B""#,
+                r#"name: pattern is "^[a-z]+$", got "B""#
+            ]
+        );
+        assert_eq!(
+            check(&format!(r#"{{{VALID}, "kind": "admin"}}"#)),
+            [r#"kind: not_in is ["admin"], got "admin""#]
+        );
+    }
+
+    #[test]
+    fn enums_and_lists() {
+        assert_eq!(
+            check(&format!(r#"{{{VALID}, "color": 7}}"#)),
+            ["color: defined_only is true, got 7"]
+        );
+        assert_eq!(
+            check(&format!(r#"{{{VALID}, "tags": ["t1", "x", "t3"]}}"#)),
+            [
+                "tags: max_items is 2, got 3 items",
+                r#"tags[1]: prefix is "t", got "x""#
+            ]
+        );
+    }
+
+    #[test]
+    fn durations_and_timestamps() {
+        assert_eq!(
+            check(&format!(r#"{{{VALID}, "ttl": "0.5s"}}"#)),
+            [r#"ttl: gte is "1s", got "0.500s""#]
+        );
+        assert_eq!(
+            check(&format!(r#"{{{VALID}, "ttl": "60s"}}"#)),
+            [r#"ttl: lt is "60s", got "60s""#]
+        );
+        assert_eq!(
+            check(&format!(r#"{{{VALID}, "interval": "2s"}}"#)),
+            [r#"interval: in is ["1s", "0.500s"], got "2s""#]
+        );
+        assert_eq!(
+            check(&format!(r#"{{{VALID}, "at": "1970-01-01T00:00:00Z"}}"#)),
+            [r#"at: gt is "1970-01-01T00:16:40Z", got "1970-01-01T00:00:00Z""#]
+        );
+    }
+}